@@ -1,7 +1,10 @@
 // use crate::BlockchainConfig;
-use crate::{BdkError, Transaction};
-use bdk::bitcoin::Network;
+use crate::{BdkError, FeeRate, Transaction};
+use bdk::bitcoin::blockdata::constants::genesis_block;
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::{BlockHash, Network, Txid};
 use bdk::blockchain::any::{AnyBlockchain, AnyBlockchainConfig};
+use bdk::blockchain::esplora::convert_fee_rate;
 use bdk::blockchain::rpc::Auth as BdkAuth;
 use bdk::blockchain::rpc::RpcSyncParams as BdkRpcSyncParams;
 use bdk::blockchain::Blockchain as BdkBlockchain;
@@ -11,17 +14,27 @@ use bdk::blockchain::{
     electrum::ElectrumBlockchainConfig, esplora::EsploraBlockchainConfig,
     rpc::RpcConfig as BdkRpcConfig, ConfigurableBlockchain,
 };
-use bdk::FeeRate;
+use bdk::electrum_client::ElectrumApi;
+use bdk::FeeRate as BdkFeeRate;
+use log::{error, info};
+use std::collections::HashMap;
 use std::convert::{From, TryFrom};
+use std::ops::Deref;
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::time::{Duration, Instant};
 
 pub(crate) struct Blockchain {
     blockchain_mutex: Mutex<AnyBlockchain>,
+    fee_cache: Mutex<FeeCache>,
+    /// The network of the backend, None until it is known, see check_network.
+    backend_network: Mutex<Option<Option<Network>>>,
 }
 
 impl Blockchain {
     pub(crate) fn new(blockchain_config: BlockchainConfig) -> Result<Self, BdkError> {
+        let mut backend_network = None;
         let any_blockchain_config = match blockchain_config {
             BlockchainConfig::Electrum { config } => {
                 AnyBlockchainConfig::Electrum(ElectrumBlockchainConfig {
@@ -42,17 +55,22 @@ impl Blockchain {
                     timeout: config.timeout,
                 })
             }
-            BlockchainConfig::Rpc { config } => AnyBlockchainConfig::Rpc(BdkRpcConfig {
-                url: config.url,
-                auth: config.auth.into(),
-                network: config.network,
-                wallet_name: config.wallet_name,
-                sync_params: config.sync_params.map(|p| p.into()),
-            }),
+            BlockchainConfig::Rpc { config } => {
+                backend_network = Some(Some(config.network));
+                AnyBlockchainConfig::Rpc(BdkRpcConfig {
+                    url: config.url,
+                    auth: config.auth.into(),
+                    network: config.network,
+                    wallet_name: config.wallet_name,
+                    sync_params: config.sync_params.map(|p| p.into()),
+                })
+            }
         };
         let blockchain = AnyBlockchain::from_config(&any_blockchain_config)?;
         Ok(Self {
             blockchain_mutex: Mutex::new(blockchain),
+            fee_cache: Mutex::new(FeeCache::new()),
+            backend_network: Mutex::new(backend_network),
         })
     }
 
@@ -60,25 +78,270 @@ impl Blockchain {
         self.blockchain_mutex.lock().expect("blockchain")
     }
 
+    /// Like get_blockchain, but returns None instead of blocking if the blockchain is in use.
+    pub(crate) fn try_get_blockchain(&self) -> Option<MutexGuard<AnyBlockchain>> {
+        match self.blockchain_mutex.try_lock() {
+            Ok(blockchain) => Some(blockchain),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("blockchain"),
+        }
+    }
+
     pub(crate) fn broadcast(&self, transaction: &Transaction) -> Result<(), BdkError> {
         let tx = &transaction.internal;
-        self.get_blockchain().broadcast(tx)
+        info!("Broadcasting transaction {}", tx.txid());
+        let result = self.get_blockchain().broadcast(tx).map_err(BdkError::from);
+        if let Err(e) = &result {
+            error!("Broadcasting transaction {} failed: {}", tx.txid(), e);
+        }
+        result
+    }
+
+    /// Broadcast the transactions one after the other in the given order, and return the result
+    /// of each. Parents must come before their children, e.g. for a CPFP the parent must be
+    /// broadcast before the child spending it. A failed transaction doesn't stop the batch, the
+    /// following transactions are still broadcast and each result tells whether its transaction
+    /// was broadcast, with the error message otherwise.
+    pub(crate) fn broadcast_batch(
+        &self,
+        transactions: Vec<Arc<Transaction>>,
+    ) -> Vec<BroadcastResult> {
+        transactions
+            .iter()
+            .map(|transaction| BroadcastResult {
+                txid: transaction.internal.txid().to_string(),
+                error_message: self.broadcast(transaction).err().map(|e| e.to_string()),
+            })
+            .collect()
     }
 
+    /// Check that the backend is on the given network before a wallet of that network syncs with
+    /// it, returning BdkError.InvalidNetwork otherwise. The network of an RPC backend is the one of
+    /// its config. Electrum and Esplora backends are asked for their genesis block once. Backends
+    /// on networks this library doesn't know, e.g. testnet4 or a custom signet, are accepted.
+    pub(crate) fn check_network(
+        &self,
+        blockchain: &AnyBlockchain,
+        network: Network,
+    ) -> Result<(), BdkError> {
+        let mut backend_network = self.backend_network.lock().expect("backend network");
+        if backend_network.is_none() {
+            let genesis_hash = blockchain.get_block_hash(0)?;
+            *backend_network = Some(network_from_genesis(&genesis_hash));
+        }
+        match *backend_network {
+            Some(Some(found)) if found != network => {
+                Err(BdkError::from(bdk::Error::InvalidNetwork {
+                    requested: network,
+                    found,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn get_fee_cache(&self) -> MutexGuard<FeeCache> {
+        self.fee_cache.lock().expect("fee cache")
+    }
+
+    /// Estimate the fee rate required to confirm a transaction within `target` blocks. Backends
+    /// can fail to estimate a fee rate, e.g. on regtest or a freshly started node, in which case
+    /// FeeRate.default_min_relay can be used as a fallback. Estimates are cached for each target,
+    /// see set_fee_cache_ttl.
     pub(crate) fn estimate_fee(&self, target: u64) -> Result<Arc<FeeRate>, BdkError> {
-        let result: Result<FeeRate, bdk::Error> =
+        if let Some(fee_rate) = self.get_fee_cache().get(target) {
+            return Ok(Arc::new(fee_rate));
+        }
+        let result: Result<BdkFeeRate, bdk::Error> =
             self.get_blockchain().estimate_fee(target as usize);
-        result.map(Arc::new)
+        let fee_rate = FeeRate::from(result?);
+        self.get_fee_cache().insert(target, fee_rate);
+        Ok(Arc::new(fee_rate))
+    }
+
+    /// Estimate the fee rates for several confirmation targets at once, e.g. to offer slow, normal
+    /// and fast fee options. Electrum and Esplora backends fetch all the estimates in a single
+    /// round trip. Estimates are cached like the ones of estimate_fee.
+    pub(crate) fn estimate_fees(&self, targets: Vec<u64>) -> Result<Vec<FeeEstimate>, BdkError> {
+        let mut fee_rates: HashMap<u64, FeeRate> = HashMap::new();
+        let mut missing: Vec<u64> = Vec::new();
+        {
+            let fee_cache = self.get_fee_cache();
+            for &target in &targets {
+                match fee_cache.get(target) {
+                    Some(fee_rate) => {
+                        fee_rates.insert(target, fee_rate);
+                    }
+                    None if !missing.contains(&target) => missing.push(target),
+                    None => {}
+                }
+            }
+        }
+        if !missing.is_empty() {
+            let fetched = self.fetch_fee_rates(&missing)?;
+            let mut fee_cache = self.get_fee_cache();
+            for (target, fee_rate) in missing.into_iter().zip(fetched) {
+                fee_cache.insert(target, fee_rate);
+                fee_rates.insert(target, fee_rate);
+            }
+        }
+        Ok(targets
+            .into_iter()
+            .map(|target| FeeEstimate {
+                target,
+                fee_rate: Arc::new(fee_rates[&target]),
+            })
+            .collect())
+    }
+
+    /// Fetch the fee rate estimates of the given targets from the server, batching the requests
+    /// when the backend allows it.
+    fn fetch_fee_rates(&self, targets: &[u64]) -> Result<Vec<FeeRate>, BdkError> {
+        let blockchain = self.get_blockchain();
+        match blockchain.deref() {
+            AnyBlockchain::Electrum(electrum) => {
+                let targets: Vec<usize> = targets.iter().map(|target| *target as usize).collect();
+                let estimates = electrum.batch_estimate_fee(&targets)?;
+                Ok(estimates
+                    .into_iter()
+                    .map(|btc_per_kvb| {
+                        FeeRate::from(BdkFeeRate::from_btc_per_kvb(btc_per_kvb as f32))
+                    })
+                    .collect())
+            }
+            AnyBlockchain::Esplora(esplora) => {
+                let estimates = esplora.get_fee_estimates()?;
+                targets
+                    .iter()
+                    .map(|target| {
+                        convert_fee_rate(*target as usize, estimates.clone())
+                            .map(FeeRate::from)
+                            .map_err(BdkError::from)
+                    })
+                    .collect()
+            }
+            other => targets
+                .iter()
+                .map(|target| {
+                    other
+                        .estimate_fee(*target as usize)
+                        .map(FeeRate::from)
+                        .map_err(BdkError::from)
+                })
+                .collect(),
+        }
+    }
+
+    /// Set for how many seconds fee rate estimates are cached (default 5 seconds), 0 disables the
+    /// cache.
+    pub(crate) fn set_fee_cache_ttl(&self, seconds: u64) {
+        self.get_fee_cache().ttl = Duration::from_secs(seconds);
+    }
+
+    /// Remove all the cached fee rate estimates, so the next estimates are fetched from the server.
+    pub(crate) fn clear_fee_cache(&self) {
+        self.get_fee_cache().estimates.clear();
     }
 
     pub(crate) fn get_height(&self) -> Result<u32, BdkError> {
-        self.get_blockchain().get_height()
+        Ok(self.get_blockchain().get_height()?)
     }
 
     pub(crate) fn get_block_hash(&self, height: u32) -> Result<String, BdkError> {
         self.get_blockchain()
             .get_block_hash(u64::from(height))
             .map(|hash| hash.to_string())
+            .map_err(BdkError::from)
+    }
+
+    /// Get the raw 80 byte header of the block at the given height. Only supported by Electrum
+    /// backends, which provide it through `blockchain.block.header`.
+    pub(crate) fn get_block_header(&self, height: u32) -> Result<Vec<u8>, BdkError> {
+        match self.get_blockchain().deref() {
+            AnyBlockchain::Electrum(electrum) => Ok(electrum.block_header_raw(height as usize)?),
+            _ => Err(BdkError::Generic {
+                error_message: "Block headers can only be fetched from an Electrum blockchain"
+                    .to_string(),
+            }),
+        }
+    }
+
+    /// Get the merkle proof that the transaction is included in the block at the given height,
+    /// which can be checked against the block header's merkle root. Only supported by Electrum
+    /// backends, which provide it through `blockchain.transaction.get_merkle`.
+    pub(crate) fn get_tx_merkle_proof(
+        &self,
+        txid: String,
+        height: u32,
+    ) -> Result<MerkleProof, BdkError> {
+        let txid = Txid::from_str(&txid).map_err(|e| BdkError::Generic {
+            error_message: e.to_string(),
+        })?;
+        match self.get_blockchain().deref() {
+            AnyBlockchain::Electrum(electrum) => {
+                let proof = electrum.transaction_get_merkle(&txid, height as usize)?;
+                Ok(MerkleProof {
+                    block_height: proof.block_height as u32,
+                    position: proof.pos as u32,
+                    merkle_branch: proof.merkle.iter().map(|hash| hash.to_hex()).collect(),
+                })
+            }
+            _ => Err(BdkError::Generic {
+                error_message: "Merkle proofs can only be fetched from an Electrum blockchain"
+                    .to_string(),
+            }),
+        }
+    }
+}
+
+/// The merkle proof of a transaction's inclusion in a block, see Blockchain.get_tx_merkle_proof.
+pub struct MerkleProof {
+    /// Height of the block including the transaction.
+    pub block_height: u32,
+    /// Position of the transaction in the block.
+    pub position: u32,
+    /// Hashes of the merkle branch from the transaction up to the merkle root, hex encoded as
+    /// returned by the Electrum server.
+    pub merkle_branch: Vec<String>,
+}
+
+/// The result of broadcasting a transaction of a batch, see Blockchain.broadcast_batch.
+pub struct BroadcastResult {
+    pub txid: String,
+    /// Why the transaction couldn't be broadcast, None if it was broadcast.
+    pub error_message: Option<String>,
+}
+
+/// The fee rate estimated for a confirmation target, see Blockchain.estimate_fees.
+pub struct FeeEstimate {
+    /// The number of blocks within which the transaction should confirm.
+    pub target: u64,
+    pub fee_rate: Arc<FeeRate>,
+}
+
+/// Fee rate estimates by confirmation target, so repeated estimates don't each hit the server.
+struct FeeCache {
+    ttl: Duration,
+    estimates: HashMap<u64, (Instant, FeeRate)>,
+}
+
+impl FeeCache {
+    fn new() -> Self {
+        FeeCache {
+            ttl: Duration::from_secs(5),
+            estimates: HashMap::new(),
+        }
+    }
+
+    fn get(&self, target: u64) -> Option<FeeRate> {
+        self.estimates
+            .get(&target)
+            .filter(|(estimated_at, _)| estimated_at.elapsed() < self.ttl)
+            .map(|(_, fee_rate)| *fee_rate)
+    }
+
+    fn insert(&mut self, target: u64, fee_rate: FeeRate) {
+        self.estimates.insert(target, (Instant::now(), fee_rate));
     }
 }
 
@@ -89,7 +352,9 @@ pub struct ElectrumConfig {
     pub url: String,
     /// URL of the socks5 proxy server or a Tor service
     pub socks5: Option<String>,
-    /// Request retry count
+    /// Request retry count. A request that fails, e.g. because the connection dropped on a mobile
+    /// network, reconnects to the server and is sent again up to this many times with an
+    /// increasing delay, so a sync isn't aborted by a single dropped connection.
     pub retry: u8,
     /// Request timeout (seconds)
     pub timeout: Option<u8>,
@@ -99,6 +364,39 @@ pub struct ElectrumConfig {
     pub validate_domain: bool,
 }
 
+/// The network whose genesis block has the given hash, None for networks this library doesn't
+/// know.
+fn network_from_genesis(genesis_hash: &BlockHash) -> Option<Network> {
+    [
+        Network::Bitcoin,
+        Network::Testnet,
+        Network::Signet,
+        Network::Regtest,
+    ]
+    .iter()
+    .copied()
+    .find(|network| genesis_block(*network).block_hash() == *genesis_hash)
+}
+
+/// The public Esplora API of blockstream.info for mainnet.
+pub(crate) const BITCOIN_ESPLORA_URL: &str = "https://blockstream.info/api/";
+/// The public Esplora API of blockstream.info for testnet.
+pub(crate) const TESTNET_ESPLORA_URL: &str = "https://blockstream.info/testnet/api/";
+/// The public Esplora API of mempool.space for the default signet.
+pub(crate) const SIGNET_ESPLORA_URL: &str = "https://mempool.space/signet/api/";
+
+/// The base URL of a public Esplora server for the network, None for regtest. Signet and testnet
+/// addresses share the `tb` prefix, so a signet wallet pointed at a testnet server doesn't fail but
+/// never finds its transactions. Use this to pick a server matching the wallet's network.
+pub(crate) fn default_esplora_url(network: Network) -> Option<String> {
+    match network {
+        Network::Bitcoin => Some(BITCOIN_ESPLORA_URL.to_string()),
+        Network::Testnet => Some(TESTNET_ESPLORA_URL.to_string()),
+        Network::Signet => Some(SIGNET_ESPLORA_URL.to_string()),
+        Network::Regtest => None,
+    }
+}
+
 /// Configuration for an EsploraBlockchain
 pub struct EsploraConfig {
     /// Base URL of the esplora service
@@ -199,3 +497,138 @@ pub enum BlockchainConfig {
     /// Bitcoin Core RPC client
     Rpc { config: RpcConfig },
 }
+
+// The goal of these tests to to ensure `bdk-ffi` intermediate code correctly calls `bdk` APIs.
+// These tests should not be used to verify `bdk` behavior that is already tested in the `bdk`
+// crate.
+#[cfg(test)]
+pub(crate) mod test {
+    use crate::blockchain::{
+        default_esplora_url, network_from_genesis, Blockchain, BlockchainConfig, EsploraConfig,
+        SIGNET_ESPLORA_URL,
+    };
+    use crate::{FeeRate, Transaction};
+    use bdk::bitcoin::blockdata::constants::genesis_block;
+    use bdk::bitcoin::{BlockHash, Network, PackedLockTime, Transaction as BdkTransaction};
+    use std::str::FromStr;
+    use std::sync::Arc;
+
+    /// An Esplora blockchain which can be created offline, as it doesn't connect to the server
+    /// until it is used. Nothing listens on its port, so every request fails.
+    pub(crate) fn offline_esplora() -> Blockchain {
+        Blockchain::new(BlockchainConfig::Esplora {
+            config: EsploraConfig {
+                base_url: "http://127.0.0.1:3002".to_string(),
+                proxy: None,
+                concurrency: None,
+                stop_gap: 10,
+                timeout: None,
+            },
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_default_esplora_url() {
+        assert_eq!(
+            default_esplora_url(Network::Signet),
+            Some(SIGNET_ESPLORA_URL.to_string())
+        );
+        assert_ne!(
+            default_esplora_url(Network::Signet),
+            default_esplora_url(Network::Testnet)
+        );
+        assert_eq!(default_esplora_url(Network::Regtest), None);
+
+        // creating an esplora blockchain doesn't connect to the server
+        let blockchain = Blockchain::new(BlockchainConfig::Esplora {
+            config: EsploraConfig {
+                base_url: default_esplora_url(Network::Signet).unwrap(),
+                proxy: None,
+                concurrency: None,
+                stop_gap: 10,
+                timeout: None,
+            },
+        });
+        assert!(blockchain.is_ok());
+    }
+
+    #[test]
+    fn test_network_from_genesis() {
+        for network in [
+            Network::Bitcoin,
+            Network::Testnet,
+            Network::Signet,
+            Network::Regtest,
+        ] {
+            let genesis_hash = genesis_block(network).block_hash();
+            assert_eq!(network_from_genesis(&genesis_hash), Some(network));
+        }
+        // e.g. testnet4
+        let unknown_hash =
+            BlockHash::from_str("00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043")
+                .unwrap();
+        assert_eq!(network_from_genesis(&unknown_hash), None);
+    }
+
+    #[test]
+    fn test_fee_cache() {
+        // creating an esplora blockchain doesn't connect to the server
+        let blockchain = offline_esplora();
+        let fee_rate = FeeRate::from_sat_per_vb(12.0);
+        blockchain.get_fee_cache().insert(6, fee_rate);
+
+        // the cached estimate is returned without asking the server
+        assert_eq!(*blockchain.estimate_fee(6).unwrap(), fee_rate);
+        assert!(blockchain.get_fee_cache().get(1).is_none());
+
+        blockchain.set_fee_cache_ttl(0);
+        assert!(blockchain.get_fee_cache().get(6).is_none());
+        blockchain.set_fee_cache_ttl(60);
+        assert_eq!(blockchain.get_fee_cache().get(6), Some(fee_rate));
+
+        blockchain.clear_fee_cache();
+        assert!(blockchain.get_fee_cache().get(6).is_none());
+    }
+
+    #[test]
+    fn test_estimate_fees_cached() {
+        let blockchain = offline_esplora();
+        let fast = FeeRate::from_sat_per_vb(20.0);
+        let slow = FeeRate::from_sat_per_vb(2.0);
+        blockchain.get_fee_cache().insert(1, fast);
+        blockchain.get_fee_cache().insert(144, slow);
+
+        // all the estimates are cached, so the server isn't asked
+        let estimates = blockchain.estimate_fees(vec![144, 1, 144]).unwrap();
+        let estimates: Vec<(u64, FeeRate)> = estimates
+            .into_iter()
+            .map(|estimate| (estimate.target, *estimate.fee_rate))
+            .collect();
+        assert_eq!(estimates, vec![(144, slow), (1, fast), (144, slow)]);
+    }
+
+    #[test]
+    fn test_broadcast_batch_results() {
+        // nothing listens on the port, so every broadcast fails
+        let blockchain = offline_esplora();
+        let transactions: Vec<Arc<Transaction>> = (0..2)
+            .map(|lock_time| {
+                Arc::new(Transaction::from(BdkTransaction {
+                    version: 1,
+                    lock_time: PackedLockTime(lock_time),
+                    input: vec![],
+                    output: vec![],
+                }))
+            })
+            .collect();
+
+        // the batch isn't stopped by the first failure
+        let results = blockchain.broadcast_batch(transactions.clone());
+        assert_eq!(results.len(), 2);
+        for (result, transaction) in results.iter().zip(&transactions) {
+            assert_eq!(result.txid, transaction.internal.txid().to_string());
+            assert!(result.error_message.is_some());
+        }
+    }
+}
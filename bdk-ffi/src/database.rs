@@ -5,7 +5,8 @@ use bdk::database::any::{SledDbConfiguration, SqliteDbConfiguration};
 /// instance. Wallets that plan to offer users the ability to switch blockchain backend at runtime
 /// will find this particularly useful.
 pub enum DatabaseConfig {
-    /// Memory database has no config
+    /// Memory database has no config. Nothing is persisted, which suits wallets that are only
+    /// used to sign and never sync, see Wallet.new_no_persist.
     Memory,
     /// Simple key-value embedded database based on sled
     Sled { config: SledDbConfiguration },
@@ -1,11 +1,13 @@
-use crate::{BdkError, DescriptorPublicKey, DescriptorSecretKey};
+use crate::{BdkError, DescriptorPair, DescriptorPublicKey, DescriptorSecretKey, ScriptType};
 use bdk::bitcoin::secp256k1::Secp256k1;
-use bdk::bitcoin::util::bip32::Fingerprint;
+use bdk::bitcoin::util::bip32::{ChildNumber, DerivationPath as BdkDerivationPath, Fingerprint};
 use bdk::bitcoin::Network;
-use bdk::descriptor::{ExtendedDescriptor, IntoWalletDescriptor, KeyMap};
+use bdk::descriptor::checksum::calc_checksum;
+use bdk::descriptor::{DescriptorXKey, ExtendedDescriptor, IntoWalletDescriptor, KeyMap, Wildcard};
 use bdk::keys::{
     DescriptorPublicKey as BdkDescriptorPublicKey, DescriptorSecretKey as BdkDescriptorSecretKey,
 };
+use bdk::miniscript::descriptor::{DescriptorType, ShInner};
 use bdk::template::{
     Bip44, Bip44Public, Bip49, Bip49Public, Bip84, Bip84Public, DescriptorTemplate,
 };
@@ -21,6 +23,8 @@ pub(crate) struct Descriptor {
 }
 
 impl Descriptor {
+    /// Parse a descriptor string, with or without its checksum. A checksum must match the
+    /// descriptor, otherwise an invalid descriptor checksum error is returned.
     pub(crate) fn new(descriptor: String, network: Network) -> Result<Self, BdkError> {
         let secp = Secp256k1::new();
         let (extended_descriptor, key_map) = descriptor.into_wallet_descriptor(&secp, network)?;
@@ -183,6 +187,71 @@ impl Descriptor {
         }
     }
 
+    /// Create a legacy P2PKH descriptor `pkh(key/<0;1>/*)` from an extended key of any derivation
+    /// path, e.g. to restore an old wallet which doesn't follow BIP44. The key origin is kept.
+    pub(crate) fn new_pkh(
+        secret_key: Arc<DescriptorSecretKey>,
+        keychain_kind: KeychainKind,
+        network: Network,
+    ) -> Result<Self, BdkError> {
+        let derivable_key = secret_key.descriptor_secret_key_mutex.lock().unwrap();
+
+        match derivable_key.deref() {
+            BdkDescriptorSecretKey::XPrv(descriptor_x_key) => {
+                let key = BdkDescriptorSecretKey::XPrv(DescriptorXKey {
+                    origin: descriptor_x_key.origin.clone(),
+                    xkey: descriptor_x_key.xkey,
+                    derivation_path: descriptor_x_key
+                        .derivation_path
+                        .extend(&[keychain_child(keychain_kind)]),
+                    wildcard: Wildcard::Unhardened,
+                });
+                Self::new(format!("pkh({})", key), network)
+            }
+            BdkDescriptorSecretKey::Single(_) => Err(BdkError::Generic {
+                error_message: "The key must be an extended private key".to_string(),
+            }),
+        }
+    }
+
+    /// Create a watch-only legacy P2PKH descriptor `pkh([fingerprint/path]key/<0;1>/*)` from an
+    /// account extended public key of any derivation path, e.g. to watch an old wallet which
+    /// doesn't follow BIP44. The origin path of the key is kept, use
+    /// DescriptorPublicKey.from_string with `[fingerprint/path]tpub...` to set it.
+    pub(crate) fn new_pkh_public(
+        public_key: Arc<DescriptorPublicKey>,
+        fingerprint: String,
+        keychain_kind: KeychainKind,
+        network: Network,
+    ) -> Result<Self, BdkError> {
+        let fingerprint =
+            Fingerprint::from_str(fingerprint.as_str()).map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        let derivable_key = public_key.descriptor_public_key_mutex.lock().unwrap();
+
+        match derivable_key.deref() {
+            BdkDescriptorPublicKey::XPub(descriptor_x_key) => {
+                let origin_path = match &descriptor_x_key.origin {
+                    Some((_, origin_path)) => origin_path.clone(),
+                    None => BdkDerivationPath::master(),
+                };
+                let key = BdkDescriptorPublicKey::XPub(DescriptorXKey {
+                    origin: Some((fingerprint, origin_path)),
+                    xkey: descriptor_x_key.xkey,
+                    derivation_path: descriptor_x_key
+                        .derivation_path
+                        .extend(&[keychain_child(keychain_kind)]),
+                    wildcard: Wildcard::Unhardened,
+                });
+                Self::new(format!("pkh({})", key), network)
+            }
+            BdkDescriptorPublicKey::Single(_) => Err(BdkError::Generic {
+                error_message: "The key must be an extended public key".to_string(),
+            }),
+        }
+    }
+
     pub(crate) fn as_string_private(&self) -> String {
         let descriptor = &self.extended_descriptor;
         let key_map = &self.key_map;
@@ -192,6 +261,207 @@ impl Descriptor {
     pub(crate) fn as_string(&self) -> String {
         self.extended_descriptor.to_string()
     }
+
+    /// Like as_string, with `h` instead of `'` for the hardened derivation steps if use_h is true,
+    /// for signers that only accept one of the forms. The checksum is computed for that form.
+    pub(crate) fn as_string_with_hardened_char(&self, use_h: bool) -> String {
+        let descriptor = self.as_string();
+        if !use_h {
+            return descriptor;
+        }
+        // `'` only appears in derivation paths, unlike `h` which is also a base58 character
+        let descriptor = descriptor
+            .split('#')
+            .next()
+            .unwrap_or_default()
+            .replace('\'', "h");
+        let checksum = calc_checksum(&descriptor).expect("descriptor characters are valid");
+        format!("{}#{}", descriptor, checksum)
+    }
+
+    /// Whether the descriptor contains private keys, i.e. a wallet using it can sign, as opposed
+    /// to a watch-only descriptor.
+    pub(crate) fn has_secret(&self) -> bool {
+        !self.key_map.is_empty()
+    }
+
+    /// The type of the scripts derived from the descriptor, e.g. to tell native segwit and taproot
+    /// wallets apart. None for bare scripts and scripts that are only wrapped in pay to script
+    /// hash, like `sh(multi(...))`.
+    pub(crate) fn script_type(&self) -> Option<ScriptType> {
+        match self.extended_descriptor.desc_type() {
+            DescriptorType::Pkh => Some(ScriptType::P2pkh),
+            DescriptorType::ShWpkh => Some(ScriptType::P2shP2wpkh),
+            DescriptorType::Wpkh => Some(ScriptType::P2wpkh),
+            DescriptorType::ShWsh | DescriptorType::ShWshSortedMulti => Some(ScriptType::P2shP2wsh),
+            DescriptorType::Wsh | DescriptorType::WshSortedMulti => Some(ScriptType::P2wsh),
+            DescriptorType::Tr => Some(ScriptType::P2tr),
+            DescriptorType::Bare | DescriptorType::Sh | DescriptorType::ShSortedMulti => None,
+        }
+    }
+
+    /// Create a change descriptor for this single key descriptor, using the same account key with
+    /// the `/1/*` derivation path instead of `/0/*` in a script of the given type, e.g. to receive
+    /// change in taproot outputs. `wpkh([d34db33f/84'/1'/0']tpub.../0/*)` with ScriptType.P2tr
+    /// gives `tr([d34db33f/84'/1'/0']tpub.../1/*)`. Private keys are kept.
+    pub(crate) fn change_descriptor(
+        &self,
+        script_type: ScriptType,
+        network: Network,
+    ) -> Result<Arc<Self>, BdkError> {
+        let public_key = match &self.extended_descriptor {
+            ExtendedDescriptor::Pkh(pkh) => pkh.as_inner(),
+            ExtendedDescriptor::Wpkh(wpkh) => wpkh.as_inner(),
+            ExtendedDescriptor::Sh(sh) => match sh.as_inner() {
+                ShInner::Wpkh(wpkh) => wpkh.as_inner(),
+                _ => return Err(single_key_error()),
+            },
+            ExtendedDescriptor::Tr(tr) if tr.taptree().is_none() => tr.internal_key(),
+            _ => return Err(single_key_error()),
+        };
+        let key = match self.key_map.get(public_key) {
+            Some(secret_key) => secret_key.to_string(),
+            None => public_key.to_string(),
+        };
+        let account_key = key.strip_suffix("/0/*").ok_or_else(|| BdkError::Generic {
+            error_message: "The descriptor key must end with /0/*".to_string(),
+        })?;
+        let change_key = format!("{}/1/*", account_key);
+        let change_descriptor = match script_type {
+            ScriptType::P2pkh => format!("pkh({})", change_key),
+            ScriptType::P2shP2wpkh => format!("sh(wpkh({}))", change_key),
+            ScriptType::P2wpkh => format!("wpkh({})", change_key),
+            ScriptType::P2tr => format!("tr({})", change_key),
+            ScriptType::P2shP2wsh | ScriptType::P2wsh => {
+                return Err(BdkError::Generic {
+                    error_message: "The change script type must be a single key script type"
+                        .to_string(),
+                })
+            }
+        };
+        Descriptor::new(change_descriptor, network).map(Arc::new)
+    }
+
+    /// Whether both descriptors are the same once normalized, see PartialEq.
+    pub(crate) fn is_equal(&self, other: &Descriptor) -> bool {
+        self == other
+    }
+
+    /// Whether the two descriptors derive different scripts, so they can be used as the external
+    /// and change descriptors of the same wallet. Descriptors that only differ in their checksum,
+    /// key origin or private keys derive the same scripts. Fails if a script can't be derived
+    /// from the public keys, e.g. for a hardened wildcard.
+    pub(crate) fn is_keychain_distinct(&self, other: &Descriptor) -> Result<bool, BdkError> {
+        let secp = Secp256k1::verification_only();
+        let first_script = |descriptor: &Descriptor| {
+            descriptor
+                .extended_descriptor
+                .derived_descriptor(&secp, 0)
+                .map(|derived| derived.script_pubkey())
+                .map_err(|e| BdkError::Generic {
+                    error_message: e.to_string(),
+                })
+        };
+        Ok(first_script(self)? != first_script(other)?)
+    }
+}
+
+/// The derivation step of a keychain, `/0` for external and `/1` for internal addresses.
+fn keychain_child(keychain_kind: KeychainKind) -> ChildNumber {
+    match keychain_kind {
+        KeychainKind::External => ChildNumber::Normal { index: 0 },
+        KeychainKind::Internal => ChildNumber::Normal { index: 1 },
+    }
+}
+
+fn single_key_error() -> BdkError {
+    BdkError::Generic {
+        error_message: "The descriptor must be a single key descriptor".to_string(),
+    }
+}
+
+/// Descriptors are equal when their normalized public strings are, so a descriptor is equal to
+/// its watch-only version. Use has_secret to tell them apart.
+impl PartialEq for Descriptor {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_string() == other.as_string()
+    }
+}
+
+impl Eq for Descriptor {}
+
+/// Split a multipath descriptor, whose keys end in `/<0;1>/*`, into its external and internal
+/// descriptors, ready to be used with Wallet.new.
+pub(crate) fn new_multipath_descriptor(
+    descriptor: String,
+    network: Network,
+) -> Result<DescriptorPair, BdkError> {
+    let mut descriptors = expand_multipath(descriptor.as_str())?.into_iter();
+    match (descriptors.next(), descriptors.next(), descriptors.next()) {
+        (Some(external), Some(internal), None) => Ok(DescriptorPair {
+            external: Arc::new(Descriptor::new(external, network)?),
+            internal: Arc::new(Descriptor::new(internal, network)?),
+        }),
+        _ => Err(BdkError::Generic {
+            error_message: "Multipath descriptor must have exactly two paths".to_string(),
+        }),
+    }
+}
+
+/// Expand every `<a;b;...>` multipath step of a descriptor or key string, returning one string per
+/// path. All the multipath steps must have the same number of paths. If the descriptor has a
+/// checksum it is validated and dropped, since it doesn't apply to the expanded descriptors.
+pub(crate) fn expand_multipath(descriptor: &str) -> Result<Vec<String>, BdkError> {
+    let descriptor = match descriptor.split_once('#') {
+        Some((descriptor_without_checksum, _)) => {
+            calc_checksum(descriptor)?;
+            descriptor_without_checksum
+        }
+        None => descriptor,
+    };
+
+    // the parts of the descriptor between multipath steps, and the paths of each step
+    let mut parts = Vec::new();
+    let mut steps: Vec<Vec<&str>> = Vec::new();
+    let mut rest = descriptor;
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..]
+            .find('>')
+            .map(|end| start + end)
+            .ok_or_else(|| BdkError::Generic {
+                error_message: "Unterminated multipath step".to_string(),
+            })?;
+        parts.push(&rest[..start]);
+        steps.push(rest[start + 1..end].split(';').collect());
+        rest = &rest[end + 1..];
+    }
+    parts.push(rest);
+
+    let path_count = match steps.first() {
+        Some(paths) => paths.len(),
+        None => {
+            return Err(BdkError::Generic {
+                error_message: "Not a multipath descriptor".to_string(),
+            })
+        }
+    };
+    if path_count < 2 || steps.iter().any(|paths| paths.len() != path_count) {
+        return Err(BdkError::Generic {
+            error_message: "Multipath steps must all have the same number of paths, at least two"
+                .to_string(),
+        });
+    }
+
+    Ok((0..path_count)
+        .map(|index| {
+            let mut expanded = parts[0].to_string();
+            for (paths, part) in steps.iter().zip(parts.iter().skip(1)) {
+                expanded.push_str(paths[index]);
+                expanded.push_str(part);
+            }
+            expanded
+        })
+        .collect())
 }
 
 // The goal of these tests to to ensure `bdk-ffi` intermediate code correctly calls `bdk` APIs.
@@ -200,14 +470,17 @@ impl Descriptor {
 #[cfg(test)]
 mod test {
     use crate::database::DatabaseConfig;
+    use crate::descriptor::new_multipath_descriptor;
     use crate::*;
     use assert_matches::assert_matches;
-    use bdk::descriptor::DescriptorError::Key;
-    use bdk::keys::KeyError::InvalidNetwork;
+    use bdk::descriptor::DescriptorError;
+    use bdk::keys::KeyError;
+    use bdk::wallet::get_funded_wallet;
+    use std::sync::Mutex;
 
     fn get_descriptor_secret_key() -> DescriptorSecretKey {
         let mnemonic = Mnemonic::from_string("chaos fabric time speed sponsor all flat solution wisdom trophy crack object robot pave observe combine where aware bench orient secret primary cable detect".to_string()).unwrap();
-        DescriptorSecretKey::new(Network::Testnet, Arc::new(mnemonic), None)
+        DescriptorSecretKey::new(Network::Testnet, Arc::new(mnemonic), None).unwrap()
     }
 
     #[test]
@@ -304,12 +577,219 @@ mod test {
         let descriptor2 = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)".to_string(), Network::Bitcoin);
         // Creating a Descriptor using an extended key that doesn't match the network provided will throw and InvalidNetwork Error
         assert!(descriptor1.is_ok());
-        assert_matches!(
+        assert_eq!(
             descriptor2.unwrap_err(),
-            bdk::Error::Descriptor(Key(InvalidNetwork))
+            BdkError::from(DescriptorError::Key(KeyError::InvalidNetwork))
         )
     }
     #[test]
+    fn test_descriptor_equality_and_has_secret() {
+        let private = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)".to_string(), Network::Testnet).unwrap();
+        let public = Descriptor::new(private.as_string(), Network::Testnet).unwrap();
+        // the checksum is optional and keys are normalized
+        let public_without_checksum = Descriptor::new(
+            private.as_string().split('#').next().unwrap().to_string(),
+            Network::Testnet,
+        )
+        .unwrap();
+        let other = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/1/*)".to_string(), Network::Testnet).unwrap();
+
+        assert!(private.has_secret());
+        assert!(!public.has_secret());
+        assert!(private.is_equal(&public));
+        assert!(public.is_equal(&public_without_checksum));
+        assert!(!private.is_equal(&other));
+    }
+
+    #[test]
+    fn test_descriptor_checksum() {
+        let descriptor = Descriptor::new("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*)".to_string(), Network::Testnet).unwrap();
+        let with_checksum = descriptor.as_string();
+        let (without_checksum, checksum) = with_checksum.split_once('#').unwrap();
+        assert!(Descriptor::new(with_checksum.clone(), Network::Testnet)
+            .unwrap()
+            .is_equal(&descriptor));
+
+        let wrong_checksum = format!("{}#{}", without_checksum, "00000000");
+        assert_eq!(
+            Descriptor::new(wrong_checksum, Network::Testnet).unwrap_err(),
+            BdkError::from(DescriptorError::InvalidDescriptorChecksum)
+        );
+        // the checksum is for the descriptor as written, not a normalized form
+        let other_path = format!("{}#{}", without_checksum.replace("/0/*", "/1/*"), checksum);
+        assert_eq!(
+            Descriptor::new(other_path, Network::Testnet).unwrap_err(),
+            BdkError::from(DescriptorError::InvalidDescriptorChecksum)
+        );
+    }
+
+    #[test]
+    fn test_as_string_with_hardened_char() {
+        let descriptor = Descriptor::new("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*)".to_string(), Network::Testnet).unwrap();
+        assert_eq!(
+            descriptor.as_string_with_hardened_char(false),
+            descriptor.as_string()
+        );
+
+        let h_form = descriptor.as_string_with_hardened_char(true);
+        assert!(h_form.starts_with("wpkh([d1d04177/84h/1h/0h]tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*)#"));
+        // the checksum of the h form is valid
+        let parsed = Descriptor::new(h_form, Network::Testnet).unwrap();
+        assert!(parsed.is_equal(&descriptor));
+    }
+    #[test]
+    fn test_new_pkh() {
+        let master_key = Arc::new(get_descriptor_secret_key());
+        let secret =
+            Descriptor::new_pkh(master_key.clone(), KeychainKind::Internal, Network::Testnet)
+                .unwrap();
+        assert!(secret.has_secret());
+        assert_eq!(secret.script_type(), Some(ScriptType::P2pkh));
+
+        // the public version watches the same addresses
+        let public = Descriptor::new_pkh_public(
+            master_key.as_public(),
+            "d1d04177".to_string(),
+            KeychainKind::Internal,
+            Network::Testnet,
+        )
+        .unwrap();
+        assert!(!public.has_secret());
+        assert!(!public.is_keychain_distinct(&secret).unwrap());
+        assert!(public.as_string().starts_with("pkh([d1d04177]tpub"));
+        assert!(public.as_string().contains("/1/*)"));
+
+        // the origin of an account key is kept
+        let account_key = DescriptorPublicKey::from_string("[d1d04177/0']tpubD9oaCiP1MPmQdndm7DCD3D3QU34pWd6BbKSRedoZF1UJcNhEk3PJwkALNYkhxeTKL29oGNR7psqvT1KZydCGqUDEKXN6dVQJY2R8ooLPy8m".to_string()).unwrap();
+        let account = Descriptor::new_pkh_public(
+            Arc::new(account_key),
+            "d1d04177".to_string(),
+            KeychainKind::External,
+            Network::Testnet,
+        )
+        .unwrap();
+        assert!(account
+            .as_string()
+            .starts_with("pkh([d1d04177/0']tpubD9oaCiP1MPmQdndm7DCD3D3QU34pWd6BbKSRedoZF1UJcNhEk3PJwkALNYkhxeTKL29oGNR7psqvT1KZydCGqUDEKXN6dVQJY2R8ooLPy8m/0/*)"));
+    }
+
+    #[test]
+    fn test_script_type() {
+        let tprv = "tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE";
+        let script_type = |descriptor: String| {
+            Descriptor::new(descriptor, Network::Testnet)
+                .unwrap()
+                .script_type()
+        };
+        assert_eq!(
+            script_type(format!("pkh({}/0/*)", tprv)),
+            Some(ScriptType::P2pkh)
+        );
+        assert_eq!(
+            script_type(format!("sh(wpkh({}/0/*))", tprv)),
+            Some(ScriptType::P2shP2wpkh)
+        );
+        assert_eq!(
+            script_type(format!("wpkh({}/0/*)", tprv)),
+            Some(ScriptType::P2wpkh)
+        );
+        assert_eq!(
+            script_type(format!("wsh(sortedmulti(1,{}/0/*))", tprv)),
+            Some(ScriptType::P2wsh)
+        );
+        assert_eq!(
+            script_type(format!("tr({}/0/*)", tprv)),
+            Some(ScriptType::P2tr)
+        );
+        assert_eq!(script_type(format!("sh(pk({}/0/*))", tprv)), None);
+    }
+
+    #[test]
+    fn test_change_descriptor() {
+        let external = Descriptor::new("wpkh([d1d04177/84'/1'/0']tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)".to_string(), Network::Testnet).unwrap();
+        let change = external
+            .change_descriptor(ScriptType::P2tr, Network::Testnet)
+            .unwrap();
+        assert!(change.has_secret());
+        assert_eq!(change.script_type(), Some(ScriptType::P2tr));
+        assert_eq!(
+            change.as_string_private(),
+            Descriptor::new("tr([d1d04177/84'/1'/0']tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/1/*)".to_string(), Network::Testnet).unwrap().as_string_private()
+        );
+
+        // the public key is used for watch-only descriptors
+        let external_public = Descriptor::new(external.as_string(), Network::Testnet).unwrap();
+        let change_public = external_public
+            .change_descriptor(ScriptType::P2tr, Network::Testnet)
+            .unwrap();
+        assert!(!change_public.has_secret());
+        assert!(change_public.is_equal(&change));
+
+        assert_matches!(
+            external.change_descriptor(ScriptType::P2wsh, Network::Testnet),
+            Err(BdkError::Generic { .. })
+        );
+        assert_matches!(
+            change.change_descriptor(ScriptType::P2wpkh, Network::Testnet),
+            Err(BdkError::Generic { .. })
+        );
+    }
+
+    #[test]
+    fn test_is_keychain_distinct() {
+        let external = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)".to_string(), Network::Testnet).unwrap();
+        let internal = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/1/*)".to_string(), Network::Testnet).unwrap();
+        let external_public = Descriptor::new(external.as_string(), Network::Testnet).unwrap();
+
+        assert!(external.is_keychain_distinct(&internal).unwrap());
+        assert!(!external.is_keychain_distinct(&external_public).unwrap());
+
+        // the public keys can't derive hardened children, so the scripts can't be compared
+        let hardened_external = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*h)".to_string(), Network::Testnet).unwrap();
+        let hardened_internal = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/1/*h)".to_string(), Network::Testnet).unwrap();
+        assert_matches!(
+            hardened_external.is_keychain_distinct(&hardened_internal),
+            Err(BdkError::Generic { .. })
+        );
+        let wallet = Wallet::new(
+            Arc::new(hardened_external),
+            Some(Arc::new(hardened_internal)),
+            Network::Testnet,
+            DatabaseConfig::Memory,
+        );
+        assert!(wallet.is_ok());
+    }
+
+    #[test]
+    fn test_private_descriptor_round_trip() {
+        let private_descriptor = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let descriptor = Descriptor::new(private_descriptor.to_string(), Network::Testnet).unwrap();
+        assert!(descriptor.has_secret());
+        let as_string_private = descriptor.as_string_private();
+        assert!(as_string_private.starts_with(&format!("{}#", private_descriptor)));
+        assert!(!descriptor.as_string().contains("tprv"));
+
+        let round_trip = Descriptor::new(as_string_private.clone(), Network::Testnet).unwrap();
+        assert!(round_trip.has_secret());
+        assert!(round_trip.is_equal(&descriptor));
+        assert_eq!(round_trip.as_string_private(), as_string_private);
+
+        // a wallet with the private descriptor can sign
+        let (funded_wallet, _, _) = get_funded_wallet(&as_string_private);
+        let wallet = Wallet {
+            wallet_mutex: Mutex::new(funded_wallet),
+        };
+        let recipient = Address::new("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt".to_string())
+            .unwrap()
+            .script_pubkey();
+        let psbt = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .finish(&wallet)
+            .unwrap()
+            .psbt;
+        assert!(wallet.sign(&psbt, None).unwrap());
+    }
+    #[test]
     fn test_wallet_from_descriptor() {
         let descriptor1 = Descriptor::new("wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)".to_string(), Network::Testnet).unwrap();
         let wallet1 = Wallet::new(
@@ -326,9 +806,23 @@ mod test {
         );
         // Creating a wallet using a Descriptor with an extended key that doesn't match the network provided in the wallet constructor will throw and InvalidNetwork Error
         assert!(wallet1.is_ok());
-        assert_matches!(
+        assert_eq!(
             wallet2.unwrap_err(),
-            bdk::Error::Descriptor(Key(InvalidNetwork))
+            BdkError::from(DescriptorError::Key(KeyError::InvalidNetwork))
         )
     }
+    #[test]
+    fn test_new_multipath_descriptor() {
+        let descriptor_pair = new_multipath_descriptor("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/<0;1>/*)".to_string(), Network::Testnet).unwrap();
+        assert!(descriptor_pair.external.as_string().starts_with("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*)#"));
+        assert!(descriptor_pair.internal.as_string().starts_with("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/1/*)#"));
+
+        // a descriptor without multipath steps can't be split
+        let not_multipath = new_multipath_descriptor("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*)".to_string(), Network::Testnet);
+        assert_matches!(not_multipath, Err(BdkError::Generic { .. }));
+
+        // there is no keychain for a third path
+        let three_paths = new_multipath_descriptor("wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/<0;1;2>/*)".to_string(), Network::Testnet);
+        assert_matches!(three_paths, Err(BdkError::Generic { .. }));
+    }
 }
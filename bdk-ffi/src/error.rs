@@ -0,0 +1,299 @@
+use bdk::bitcoincore_rpc::jsonrpc::serde_json;
+use std::fmt;
+
+/// Errors returned to the bindings. The variants are the ones of the BDK error, each with the
+/// error message, except for InsufficientFunds which has the amounts so apps can show how much is
+/// missing without parsing the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BdkError {
+    InvalidU32Bytes {
+        error_message: String,
+    },
+    Generic {
+        error_message: String,
+    },
+    MissingCachedScripts {
+        error_message: String,
+    },
+    ScriptDoesntHaveAddressForm {
+        error_message: String,
+    },
+    NoRecipients {
+        error_message: String,
+    },
+    NoUtxosSelected {
+        error_message: String,
+    },
+    OutputBelowDustLimit {
+        error_message: String,
+    },
+    /// The wallet doesn't have enough funds, both amounts are in satoshi.
+    InsufficientFunds {
+        needed: u64,
+        available: u64,
+    },
+    BnBTotalTriesExceeded {
+        error_message: String,
+    },
+    BnBNoExactMatch {
+        error_message: String,
+    },
+    UnknownUtxo {
+        error_message: String,
+    },
+    TransactionNotFound {
+        error_message: String,
+    },
+    TransactionConfirmed {
+        error_message: String,
+    },
+    IrreplaceableTransaction {
+        error_message: String,
+    },
+    FeeRateTooLow {
+        error_message: String,
+    },
+    FeeTooLow {
+        error_message: String,
+    },
+    FeeRateUnavailable {
+        error_message: String,
+    },
+    MissingKeyOrigin {
+        error_message: String,
+    },
+    Key {
+        error_message: String,
+    },
+    ChecksumMismatch {
+        error_message: String,
+    },
+    SpendingPolicyRequired {
+        error_message: String,
+    },
+    InvalidPolicyPathError {
+        error_message: String,
+    },
+    Signer {
+        error_message: String,
+    },
+    InvalidNetwork {
+        error_message: String,
+    },
+    InvalidProgressValue {
+        error_message: String,
+    },
+    ProgressUpdateError {
+        error_message: String,
+    },
+    InvalidOutpoint {
+        error_message: String,
+    },
+    Descriptor {
+        error_message: String,
+    },
+    Encode {
+        error_message: String,
+    },
+    Miniscript {
+        error_message: String,
+    },
+    MiniscriptPsbt {
+        error_message: String,
+    },
+    Bip32 {
+        error_message: String,
+    },
+    Secp256k1 {
+        error_message: String,
+    },
+    Json {
+        error_message: String,
+    },
+    Hex {
+        error_message: String,
+    },
+    Psbt {
+        error_message: String,
+    },
+    PsbtParse {
+        error_message: String,
+    },
+    Electrum {
+        error_message: String,
+    },
+    Esplora {
+        error_message: String,
+    },
+    Sled {
+        error_message: String,
+    },
+    Rusqlite {
+        error_message: String,
+    },
+    Rpc {
+        error_message: String,
+    },
+}
+
+impl fmt::Display for BdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BdkError::InsufficientFunds { needed, available } => write!(
+                f,
+                "Insufficient funds: {} sat available of {} sat needed",
+                available, needed
+            ),
+            BdkError::InvalidU32Bytes { error_message }
+            | BdkError::Generic { error_message }
+            | BdkError::MissingCachedScripts { error_message }
+            | BdkError::ScriptDoesntHaveAddressForm { error_message }
+            | BdkError::NoRecipients { error_message }
+            | BdkError::NoUtxosSelected { error_message }
+            | BdkError::OutputBelowDustLimit { error_message }
+            | BdkError::BnBTotalTriesExceeded { error_message }
+            | BdkError::BnBNoExactMatch { error_message }
+            | BdkError::UnknownUtxo { error_message }
+            | BdkError::TransactionNotFound { error_message }
+            | BdkError::TransactionConfirmed { error_message }
+            | BdkError::IrreplaceableTransaction { error_message }
+            | BdkError::FeeRateTooLow { error_message }
+            | BdkError::FeeTooLow { error_message }
+            | BdkError::FeeRateUnavailable { error_message }
+            | BdkError::MissingKeyOrigin { error_message }
+            | BdkError::Key { error_message }
+            | BdkError::ChecksumMismatch { error_message }
+            | BdkError::SpendingPolicyRequired { error_message }
+            | BdkError::InvalidPolicyPathError { error_message }
+            | BdkError::Signer { error_message }
+            | BdkError::InvalidNetwork { error_message }
+            | BdkError::InvalidProgressValue { error_message }
+            | BdkError::ProgressUpdateError { error_message }
+            | BdkError::InvalidOutpoint { error_message }
+            | BdkError::Descriptor { error_message }
+            | BdkError::Encode { error_message }
+            | BdkError::Miniscript { error_message }
+            | BdkError::MiniscriptPsbt { error_message }
+            | BdkError::Bip32 { error_message }
+            | BdkError::Secp256k1 { error_message }
+            | BdkError::Json { error_message }
+            | BdkError::Hex { error_message }
+            | BdkError::Psbt { error_message }
+            | BdkError::PsbtParse { error_message }
+            | BdkError::Electrum { error_message }
+            | BdkError::Esplora { error_message }
+            | BdkError::Sled { error_message }
+            | BdkError::Rusqlite { error_message }
+            | BdkError::Rpc { error_message } => write!(f, "{}", error_message),
+        }
+    }
+}
+
+impl std::error::Error for BdkError {}
+
+impl From<bdk::Error> for BdkError {
+    fn from(error: bdk::Error) -> Self {
+        let error_message = error.to_string();
+        match error {
+            bdk::Error::InsufficientFunds { needed, available } => {
+                BdkError::InsufficientFunds { needed, available }
+            }
+            // the message of a generic error is only the inner message, like the ones of bdk-ffi
+            bdk::Error::Generic(error_message) => BdkError::Generic { error_message },
+            bdk::Error::InvalidU32Bytes { .. } => BdkError::InvalidU32Bytes { error_message },
+            bdk::Error::MissingCachedScripts { .. } => {
+                BdkError::MissingCachedScripts { error_message }
+            }
+            bdk::Error::ScriptDoesntHaveAddressForm { .. } => {
+                BdkError::ScriptDoesntHaveAddressForm { error_message }
+            }
+            bdk::Error::NoRecipients { .. } => BdkError::NoRecipients { error_message },
+            bdk::Error::NoUtxosSelected { .. } => BdkError::NoUtxosSelected { error_message },
+            bdk::Error::OutputBelowDustLimit { .. } => {
+                BdkError::OutputBelowDustLimit { error_message }
+            }
+            bdk::Error::BnBTotalTriesExceeded { .. } => {
+                BdkError::BnBTotalTriesExceeded { error_message }
+            }
+            bdk::Error::BnBNoExactMatch { .. } => BdkError::BnBNoExactMatch { error_message },
+            bdk::Error::UnknownUtxo { .. } => BdkError::UnknownUtxo { error_message },
+            bdk::Error::TransactionNotFound { .. } => {
+                BdkError::TransactionNotFound { error_message }
+            }
+            bdk::Error::TransactionConfirmed { .. } => {
+                BdkError::TransactionConfirmed { error_message }
+            }
+            bdk::Error::IrreplaceableTransaction { .. } => {
+                BdkError::IrreplaceableTransaction { error_message }
+            }
+            bdk::Error::FeeRateTooLow { .. } => BdkError::FeeRateTooLow { error_message },
+            bdk::Error::FeeTooLow { .. } => BdkError::FeeTooLow { error_message },
+            bdk::Error::FeeRateUnavailable { .. } => BdkError::FeeRateUnavailable { error_message },
+            bdk::Error::MissingKeyOrigin { .. } => BdkError::MissingKeyOrigin { error_message },
+            bdk::Error::Key { .. } => BdkError::Key { error_message },
+            bdk::Error::ChecksumMismatch { .. } => BdkError::ChecksumMismatch { error_message },
+            bdk::Error::SpendingPolicyRequired { .. } => {
+                BdkError::SpendingPolicyRequired { error_message }
+            }
+            bdk::Error::InvalidPolicyPathError { .. } => {
+                BdkError::InvalidPolicyPathError { error_message }
+            }
+            bdk::Error::Signer { .. } => BdkError::Signer { error_message },
+            bdk::Error::InvalidNetwork { .. } => BdkError::InvalidNetwork { error_message },
+            bdk::Error::InvalidProgressValue { .. } => {
+                BdkError::InvalidProgressValue { error_message }
+            }
+            bdk::Error::ProgressUpdateError { .. } => {
+                BdkError::ProgressUpdateError { error_message }
+            }
+            bdk::Error::InvalidOutpoint { .. } => BdkError::InvalidOutpoint { error_message },
+            bdk::Error::Descriptor { .. } => BdkError::Descriptor { error_message },
+            bdk::Error::Encode { .. } => BdkError::Encode { error_message },
+            bdk::Error::Miniscript { .. } => BdkError::Miniscript { error_message },
+            bdk::Error::MiniscriptPsbt { .. } => BdkError::MiniscriptPsbt { error_message },
+            bdk::Error::Bip32 { .. } => BdkError::Bip32 { error_message },
+            bdk::Error::Secp256k1 { .. } => BdkError::Secp256k1 { error_message },
+            bdk::Error::Json { .. } => BdkError::Json { error_message },
+            bdk::Error::Hex { .. } => BdkError::Hex { error_message },
+            bdk::Error::Psbt { .. } => BdkError::Psbt { error_message },
+            bdk::Error::PsbtParse { .. } => BdkError::PsbtParse { error_message },
+            bdk::Error::Electrum { .. } => BdkError::Electrum { error_message },
+            bdk::Error::Esplora { .. } => BdkError::Esplora { error_message },
+            bdk::Error::Sled { .. } => BdkError::Sled { error_message },
+            bdk::Error::Rusqlite { .. } => BdkError::Rusqlite { error_message },
+            bdk::Error::Rpc { .. } => BdkError::Rpc { error_message },
+        }
+    }
+}
+
+/// Convert the errors BDK converts into its own error, so `?` works on them like it did when the
+/// BDK error was used directly.
+macro_rules! impl_from_bdk_error {
+    ($($error:ty),* $(,)?) => {
+        $(
+            impl From<$error> for BdkError {
+                fn from(error: $error) -> Self {
+                    BdkError::from(bdk::Error::from(error))
+                }
+            }
+        )*
+    };
+}
+
+impl_from_bdk_error!(
+    bdk::keys::KeyError,
+    bdk::descriptor::DescriptorError,
+    bdk::descriptor::policy::PolicyError,
+    bdk::signer::SignerError,
+    bdk::bitcoin::consensus::encode::Error,
+    bdk::miniscript::Error,
+    bdk::bitcoin::util::bip32::Error,
+    bdk::bitcoin::secp256k1::Error,
+    serde_json::Error,
+    bdk::bitcoin::hashes::hex::Error,
+    bdk::bitcoin::util::psbt::Error,
+    bdk::bitcoin::util::psbt::PsbtParseError,
+    bdk::electrum_client::Error,
+    bdk::blockchain::esplora::EsploraError,
+    bdk::bitcoincore_rpc::Error,
+);
@@ -1,15 +1,18 @@
-use crate::BdkError;
+use crate::descriptor::expand_multipath;
+use crate::{Address, BdkError, ScriptType};
 
 use bdk::bitcoin::secp256k1::Secp256k1;
-use bdk::bitcoin::util::bip32::DerivationPath as BdkDerivationPath;
-use bdk::bitcoin::Network;
+use bdk::bitcoin::util::base58;
+use bdk::bitcoin::util::bip32::{ChildNumber, DerivationPath as BdkDerivationPath, Fingerprint};
+use bdk::bitcoin::{Address as BdkAddress, Network};
 use bdk::descriptor::DescriptorXKey;
-use bdk::keys::bip39::{Language, Mnemonic as BdkMnemonic, WordCount};
+use bdk::keys::bip39::{Language, Mnemonic as BdkMnemonic, Seed, WordCount};
 use bdk::keys::{
     DerivableKey, DescriptorPublicKey as BdkDescriptorPublicKey,
     DescriptorSecretKey as BdkDescriptorSecretKey, ExtendedKey, GeneratableKey, GeneratedKey,
 };
 use bdk::miniscript::BareCtx;
+use std::borrow::Cow;
 use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -33,7 +36,9 @@ impl Mnemonic {
     pub(crate) fn from_string(mnemonic: String) -> Result<Self, BdkError> {
         BdkMnemonic::from_str(&mnemonic)
             .map(|m| Mnemonic { internal: m })
-            .map_err(|e| BdkError::Generic(e.to_string()))
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })
     }
 
     /// Create a new Mnemonic in the specified language from the given entropy.
@@ -41,7 +46,48 @@ impl Mnemonic {
     pub(crate) fn from_entropy(entropy: Vec<u8>) -> Result<Self, BdkError> {
         BdkMnemonic::from_entropy(entropy.as_slice())
             .map(|m| Mnemonic { internal: m })
-            .map_err(|e| BdkError::Generic(e.to_string()))
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })
+    }
+
+    /// Create a new Mnemonic from caller-supplied entropy (e.g. a hardware RNG or dice rolls),
+    /// checking that the entropy length matches the requested number of words.
+    pub(crate) fn from_entropy_with_count(
+        word_count: WordCount,
+        entropy: Vec<u8>,
+    ) -> Result<Self, BdkError> {
+        let (words, expected_len) = match word_count {
+            WordCount::Words12 => (12, 16),
+            WordCount::Words15 => (15, 20),
+            WordCount::Words18 => (18, 24),
+            WordCount::Words21 => (21, 28),
+            WordCount::Words24 => (24, 32),
+        };
+        if entropy.len() != expected_len {
+            return Err(BdkError::Generic {
+                error_message: format!(
+                    "{} words require {} bytes of entropy, got {} bytes",
+                    words,
+                    expected_len,
+                    entropy.len()
+                ),
+            });
+        }
+        Mnemonic::from_entropy(entropy)
+    }
+
+    /// The 64 byte BIP39 seed derived with PBKDF2 from the mnemonic and the optional passphrase,
+    /// which is NFKD normalized first. This is the seed the master key is derived from, not the
+    /// entropy the mnemonic words encode (see from_entropy).
+    pub(crate) fn to_seed(&self, passphrase: Option<String>) -> Vec<u8> {
+        self.seed(passphrase).to_vec()
+    }
+
+    fn seed(&self, passphrase: Option<String>) -> Seed {
+        let mut passphrase = Cow::from(passphrase.unwrap_or_default());
+        BdkMnemonic::normalize_utf8_cow(&mut passphrase);
+        self.internal.to_seed_normalized(&passphrase)
     }
 
     /// Returns Mnemonic as string
@@ -60,7 +106,9 @@ impl DerivationPath {
             .map(|x| DerivationPath {
                 derivation_path_mutex: Mutex::new(x),
             })
-            .map_err(|e| BdkError::Generic(e.to_string()))
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })
     }
 }
 
@@ -70,23 +118,44 @@ pub(crate) struct DescriptorSecretKey {
 }
 
 impl DescriptorSecretKey {
-    pub(crate) fn new(network: Network, mnemonic: Arc<Mnemonic>, password: Option<String>) -> Self {
-        let mnemonic = mnemonic.internal.clone();
-        let xkey: ExtendedKey = (mnemonic, password).into_extended_key().unwrap();
+    /// Create the master key of a mnemonic and optional BIP-39 passphrase. The passphrase is NFKD
+    /// normalized as BIP-39 specifies, so equivalent unicode strings give the same key.
+    pub(crate) fn new(
+        network: Network,
+        mnemonic: Arc<Mnemonic>,
+        password: Option<String>,
+    ) -> Result<Self, BdkError> {
+        let seed = mnemonic.seed(password);
+        let xkey: ExtendedKey = seed.into_extended_key()?;
+        let xprv = xkey.into_xprv(network).ok_or_else(|| BdkError::Generic {
+            error_message: "The mnemonic doesn't give an extended private key".to_string(),
+        })?;
         let descriptor_secret_key = BdkDescriptorSecretKey::XPrv(DescriptorXKey {
             origin: None,
-            xkey: xkey.into_xprv(network).unwrap(),
+            xkey: xprv,
             derivation_path: BdkDerivationPath::master(),
             wildcard: bdk::descriptor::Wildcard::Unhardened,
         });
-        Self {
+        Ok(Self {
             descriptor_secret_key_mutex: Mutex::new(descriptor_secret_key),
-        }
+        })
     }
 
+    /// Parse a descriptor secret key. A bare extended private key, without a derivation path or
+    /// wildcard, is treated as a derivable key with an unhardened wildcard, like the master key
+    /// created by new.
     pub(crate) fn from_string(private_key: String) -> Result<Self, BdkError> {
-        let descriptor_secret_key = BdkDescriptorSecretKey::from_str(private_key.as_str())
-            .map_err(|e| BdkError::Generic(e.to_string()))?;
+        let mut descriptor_secret_key = BdkDescriptorSecretKey::from_str(private_key.as_str())
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        if let BdkDescriptorSecretKey::XPrv(descriptor_x_key) = &mut descriptor_secret_key {
+            if descriptor_x_key.derivation_path.is_master()
+                && descriptor_x_key.wildcard == bdk::descriptor::Wildcard::None
+            {
+                descriptor_x_key.wildcard = bdk::descriptor::Wildcard::Unhardened;
+            }
+        }
         Ok(Self {
             descriptor_secret_key_mutex: Mutex::new(descriptor_secret_key),
         })
@@ -113,9 +182,9 @@ impl DescriptorSecretKey {
                     descriptor_secret_key_mutex: Mutex::new(derived_descriptor_secret_key),
                 }))
             }
-            BdkDescriptorSecretKey::Single(_) => Err(BdkError::Generic(
-                "Cannot derive from a single key".to_string(),
-            )),
+            BdkDescriptorSecretKey::Single(_) => Err(BdkError::Generic {
+                error_message: "Cannot derive from a single key".to_string(),
+            }),
         }
     }
 
@@ -135,22 +204,30 @@ impl DescriptorSecretKey {
                     descriptor_secret_key_mutex: Mutex::new(extended_descriptor_secret_key),
                 }))
             }
-            BdkDescriptorSecretKey::Single(_) => Err(BdkError::Generic(
-                "Cannot extend from a single key".to_string(),
-            )),
+            BdkDescriptorSecretKey::Single(_) => Err(BdkError::Generic {
+                error_message: "Cannot extend from a single key".to_string(),
+            }),
         }
     }
 
+    /// Get the public version of the key. A master key without an origin gets its own fingerprint
+    /// as origin, e.g. `[d1d04177]tpub...`, so descriptors built from it can be imported by other
+    /// wallets. The origin of keys that aren't master keys can only be set with with_origin.
     pub(crate) fn as_public(&self) -> Arc<DescriptorPublicKey> {
         let secp = Secp256k1::new();
-        let descriptor_public_key = self
-            .descriptor_secret_key_mutex
-            .lock()
-            .unwrap()
-            .to_public(&secp)
-            .unwrap();
+        let mut descriptor_secret_key = self.descriptor_secret_key_mutex.lock().unwrap().clone();
+        if let BdkDescriptorSecretKey::XPrv(descriptor_x_key) = &mut descriptor_secret_key {
+            if descriptor_x_key.origin.is_none() && descriptor_x_key.xkey.depth == 0 {
+                descriptor_x_key.origin = Some((
+                    descriptor_x_key.xkey.fingerprint(&secp),
+                    BdkDerivationPath::master(),
+                ));
+            }
+        }
+        let descriptor_public_key = descriptor_secret_key.to_public(&secp).unwrap();
         Arc::new(DescriptorPublicKey {
             descriptor_public_key_mutex: Mutex::new(descriptor_public_key),
+            script_type: None,
         })
     }
 
@@ -174,17 +251,95 @@ impl DescriptorSecretKey {
     }
 }
 
+/// Expand a multipath descriptor public key, e.g. `xpub.../<0;1>/*`, into one key per path, such as
+/// the keys of the external and internal descriptors. Keys are parsed like with
+/// DescriptorPublicKey.from_string.
+pub(crate) fn expand_multipath_public_key(
+    public_key: String,
+) -> Result<Vec<Arc<DescriptorPublicKey>>, BdkError> {
+    expand_multipath(public_key.as_str())?
+        .into_iter()
+        .map(|public_key| DescriptorPublicKey::from_string(public_key).map(Arc::new))
+        .collect()
+}
+
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+/// SLIP-0132 extended public key versions, the script type they imply and whether they are used on
+/// test networks.
+const SLIP132_VERSIONS: [([u8; 4], ScriptType, bool); 8] = [
+    ([0x04, 0x9d, 0x7c, 0xb2], ScriptType::P2shP2wpkh, false), // ypub
+    ([0x04, 0xb2, 0x47, 0x46], ScriptType::P2wpkh, false),     // zpub
+    ([0x02, 0x95, 0xb4, 0x3f], ScriptType::P2shP2wsh, false),  // Ypub
+    ([0x02, 0xaa, 0x7e, 0xd3], ScriptType::P2wsh, false),      // Zpub
+    ([0x04, 0x4a, 0x52, 0x62], ScriptType::P2shP2wpkh, true),  // upub
+    ([0x04, 0x5f, 0x1c, 0xf6], ScriptType::P2wpkh, true),      // vpub
+    ([0x02, 0x42, 0x89, 0xef], ScriptType::P2shP2wsh, true),   // Upub
+    ([0x02, 0x57, 0x54, 0x83], ScriptType::P2wsh, true),       // Vpub
+];
+
+/// Replace a SLIP-0132 extended public key (ypub, zpub, ...) in a descriptor public key string
+/// with the equivalent xpub or tpub, returning the script type implied by its version.
+fn convert_slip132(public_key: &str) -> Result<(String, Option<ScriptType>), BdkError> {
+    let key_start = public_key.find(']').map_or(0, |i| i + 1);
+    let key_end = public_key[key_start..]
+        .find('/')
+        .map_or(public_key.len(), |i| key_start + i);
+    let key = &public_key[key_start..key_end];
+    // single public keys are hex encoded
+    if key.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok((public_key.to_string(), None));
+    }
+    let mut data = match base58::from_check(key) {
+        Ok(data) if data.len() == 78 => data,
+        _ => return Ok((public_key.to_string(), None)),
+    };
+    let version = [data[0], data[1], data[2], data[3]];
+    if version == XPUB_VERSION || version == TPUB_VERSION {
+        return Ok((public_key.to_string(), None));
+    }
+    let (script_type, is_testnet) = SLIP132_VERSIONS
+        .iter()
+        .find(|(slip132_version, _, _)| *slip132_version == version)
+        .map(|(_, script_type, is_testnet)| (*script_type, *is_testnet))
+        .ok_or_else(|| BdkError::Generic {
+            error_message: format!("Unrecognized extended public key prefix: {}", &key[..4]),
+        })?;
+    data[..4].copy_from_slice(if is_testnet {
+        &TPUB_VERSION
+    } else {
+        &XPUB_VERSION
+    });
+    let converted = format!(
+        "{}{}{}",
+        &public_key[..key_start],
+        base58::check_encode_slice(&data),
+        &public_key[key_end..]
+    );
+    Ok((converted, Some(script_type)))
+}
+
 #[derive(Debug)]
 pub(crate) struct DescriptorPublicKey {
     pub(crate) descriptor_public_key_mutex: Mutex<BdkDescriptorPublicKey>,
+    script_type: Option<ScriptType>,
 }
 
 impl DescriptorPublicKey {
+    /// Parse a descriptor public key. SLIP-0132 extended public keys (ypub, zpub, Ypub, Zpub and
+    /// their testnet equivalents) are converted to the standard xpub/tpub form.
     pub(crate) fn from_string(public_key: String) -> Result<Self, BdkError> {
-        let descriptor_public_key = BdkDescriptorPublicKey::from_str(public_key.as_str())
-            .map_err(|e| BdkError::Generic(e.to_string()))?;
+        let (public_key, script_type) = convert_slip132(public_key.as_str())?;
+        let descriptor_public_key =
+            BdkDescriptorPublicKey::from_str(public_key.as_str()).map_err(|e| {
+                BdkError::Generic {
+                    error_message: e.to_string(),
+                }
+            })?;
         Ok(Self {
             descriptor_public_key_mutex: Mutex::new(descriptor_public_key),
+            script_type,
         })
     }
 
@@ -194,6 +349,11 @@ impl DescriptorPublicKey {
         let path = path.derivation_path_mutex.lock().unwrap().deref().clone();
 
         match descriptor_public_key.deref() {
+            BdkDescriptorPublicKey::XPub(_) if path.into_iter().any(|c| c.is_hardened()) => {
+                Err(BdkError::Generic {
+                    error_message: "Cannot derive a hardened child from a public key".to_string(),
+                })
+            }
             BdkDescriptorPublicKey::XPub(descriptor_x_key) => {
                 let derived_xpub = descriptor_x_key.xkey.derive_pub(&secp, &path)?;
                 let key_source = match descriptor_x_key.origin.clone() {
@@ -208,11 +368,12 @@ impl DescriptorPublicKey {
                 });
                 Ok(Arc::new(Self {
                     descriptor_public_key_mutex: Mutex::new(derived_descriptor_public_key),
+                    script_type: self.script_type,
                 }))
             }
-            BdkDescriptorPublicKey::Single(_) => Err(BdkError::Generic(
-                "Cannot derive from a single key".to_string(),
-            )),
+            BdkDescriptorPublicKey::Single(_) => Err(BdkError::Generic {
+                error_message: "Cannot derive from a single key".to_string(),
+            }),
         }
     }
 
@@ -230,14 +391,118 @@ impl DescriptorPublicKey {
                 });
                 Ok(Arc::new(Self {
                     descriptor_public_key_mutex: Mutex::new(extended_descriptor_public_key),
+                    script_type: self.script_type,
                 }))
             }
-            BdkDescriptorPublicKey::Single(_) => Err(BdkError::Generic(
-                "Cannot extend from a single key".to_string(),
-            )),
+            BdkDescriptorPublicKey::Single(_) => Err(BdkError::Generic {
+                error_message: "Cannot extend from a single key".to_string(),
+            }),
         }
     }
 
+    /// Replace the wildcard of a ranged key with `index`, e.g. to check the key of a specific
+    /// receive address against a signing device. Keys without a wildcard are returned unchanged.
+    pub(crate) fn at_derivation_index(&self, index: u32) -> Result<Arc<Self>, BdkError> {
+        ChildNumber::from_normal_idx(index).map_err(|e| BdkError::Generic {
+            error_message: e.to_string(),
+        })?;
+        let secp = Secp256k1::new();
+        let descriptor_public_key = self.descriptor_public_key_mutex.lock().unwrap().clone();
+        let definite_key = descriptor_public_key.at_derivation_index(index);
+        // a hardened wildcard can't be derived from a public key
+        definite_key
+            .derive_public_key(&secp)
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        Ok(Arc::new(Self {
+            descriptor_public_key_mutex: Mutex::new(definite_key.into()),
+            script_type: self.script_type,
+        }))
+    }
+
+    /// Set the origin of the key, i.e. the fingerprint of the master key and the derivation path
+    /// from it to this key, replacing any existing origin. The fingerprint must be 8 hex characters.
+    pub(crate) fn with_origin(
+        &self,
+        fingerprint: String,
+        path: Arc<DerivationPath>,
+    ) -> Result<Arc<Self>, BdkError> {
+        if fingerprint.len() != 8 || !fingerprint.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(BdkError::Generic {
+                error_message: format!(
+                    "Invalid fingerprint {}, expected 8 hex characters",
+                    fingerprint
+                ),
+            });
+        }
+        let fingerprint =
+            Fingerprint::from_str(fingerprint.as_str()).map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        let path = path.derivation_path_mutex.lock().unwrap().deref().clone();
+        let mut descriptor_public_key = self.descriptor_public_key_mutex.lock().unwrap().clone();
+        match &mut descriptor_public_key {
+            BdkDescriptorPublicKey::XPub(descriptor_x_key) => {
+                descriptor_x_key.origin = Some((fingerprint, path));
+            }
+            BdkDescriptorPublicKey::Single(single_pub) => {
+                single_pub.origin = Some((fingerprint, path));
+            }
+        }
+        Ok(Arc::new(Self {
+            descriptor_public_key_mutex: Mutex::new(descriptor_public_key),
+            script_type: self.script_type,
+        }))
+    }
+
+    /// Derive the address of the given single key script type at `index`, without building a
+    /// descriptor. The index replaces the wildcard of the key and is ignored for keys without one.
+    /// Only P2pkh, P2shP2wpkh, P2wpkh and P2tr (key path only) are single key script types.
+    pub(crate) fn address(
+        &self,
+        index: u32,
+        script_type: ScriptType,
+        network: Network,
+    ) -> Result<Arc<Address>, BdkError> {
+        let secp = Secp256k1::new();
+        let descriptor_public_key = self.descriptor_public_key_mutex.lock().unwrap().clone();
+        let public_key = descriptor_public_key
+            .at_derivation_index(index)
+            .derive_public_key(&secp)
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        let address = match script_type {
+            ScriptType::P2pkh => BdkAddress::p2pkh(&public_key, network),
+            ScriptType::P2shP2wpkh => {
+                BdkAddress::p2shwpkh(&public_key, network).map_err(|e| BdkError::Generic {
+                    error_message: e.to_string(),
+                })?
+            }
+            ScriptType::P2wpkh => {
+                BdkAddress::p2wpkh(&public_key, network).map_err(|e| BdkError::Generic {
+                    error_message: e.to_string(),
+                })?
+            }
+            ScriptType::P2tr => {
+                let (internal_key, _) = public_key.inner.x_only_public_key();
+                BdkAddress::p2tr(&secp, internal_key, None, network)
+            }
+            ScriptType::P2shP2wsh | ScriptType::P2wsh => {
+                return Err(BdkError::Generic {
+                    error_message: format!("{:?} is not a single key script type", script_type),
+                })
+            }
+        };
+        Ok(Arc::new(Address { address }))
+    }
+
+    /// The script type implied by the SLIP-0132 version of the key this was parsed from, if any.
+    pub(crate) fn script_type(&self) -> Option<ScriptType> {
+        self.script_type
+    }
+
     pub(crate) fn as_string(&self) -> String {
         self.descriptor_public_key_mutex.lock().unwrap().to_string()
     }
@@ -248,15 +513,19 @@ impl DescriptorPublicKey {
 // crate.
 #[cfg(test)]
 mod test {
-    use crate::keys::{DerivationPath, DescriptorPublicKey, DescriptorSecretKey, Mnemonic};
-    use crate::BdkError;
+    use crate::keys::{
+        expand_multipath_public_key, DerivationPath, DescriptorPublicKey, DescriptorSecretKey,
+        Mnemonic,
+    };
+    use crate::{BdkError, ScriptType};
     use bdk::bitcoin::hashes::hex::ToHex;
     use bdk::bitcoin::Network;
+    use bdk::keys::bip39::WordCount;
     use std::sync::Arc;
 
     fn get_descriptor_secret_key() -> DescriptorSecretKey {
         let mnemonic = Mnemonic::from_string("chaos fabric time speed sponsor all flat solution wisdom trophy crack object robot pave observe combine where aware bench orient secret primary cable detect".to_string()).unwrap();
-        DescriptorSecretKey::new(Network::Testnet, Arc::new(mnemonic), None)
+        DescriptorSecretKey::new(Network::Testnet, Arc::new(mnemonic), None).unwrap()
     }
 
     fn derive_dsk(
@@ -291,11 +560,49 @@ mod test {
         key.extend(path)
     }
 
+    #[test]
+    fn test_descriptor_secret_key_from_bare_xprv() {
+        let master_dsk = get_descriptor_secret_key();
+        let imported_dsk = DescriptorSecretKey::from_string("tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h".to_string()).unwrap();
+        assert_eq!(imported_dsk.as_string(), master_dsk.as_string());
+        assert_eq!(imported_dsk.as_string(), "tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/*");
+
+        // keys with a derivation path are left as they are
+        let imported_dsk = DescriptorSecretKey::from_string("tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/0".to_string()).unwrap();
+        assert_eq!(imported_dsk.as_string(), "tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/0");
+    }
+
+    #[test]
+    fn test_descriptor_secret_key_passphrase_normalization() {
+        let mnemonic = Arc::new(Mnemonic::from_string("chaos fabric time speed sponsor all flat solution wisdom trophy crack object robot pave observe combine where aware bench orient secret primary cable detect".to_string()).unwrap());
+        // "café" with a precomposed é and with an e followed by a combining acute accent
+        let composed = DescriptorSecretKey::new(
+            Network::Testnet,
+            mnemonic.clone(),
+            Some("caf\u{e9}".to_string()),
+        )
+        .unwrap();
+        let decomposed = DescriptorSecretKey::new(
+            Network::Testnet,
+            mnemonic.clone(),
+            Some("cafe\u{301}".to_string()),
+        )
+        .unwrap();
+        assert_eq!(composed.as_string(), decomposed.as_string());
+
+        let without_passphrase =
+            DescriptorSecretKey::new(Network::Testnet, mnemonic.clone(), None).unwrap();
+        let empty_passphrase =
+            DescriptorSecretKey::new(Network::Testnet, mnemonic, Some("".to_string())).unwrap();
+        assert_eq!(without_passphrase.as_string(), empty_passphrase.as_string());
+        assert_ne!(composed.as_string(), without_passphrase.as_string());
+    }
+
     #[test]
     fn test_generate_descriptor_secret_key() {
         let master_dsk = get_descriptor_secret_key();
         assert_eq!(master_dsk.as_string(), "tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/*");
-        assert_eq!(master_dsk.as_public().as_string(), "tpubD6NzVbkrYhZ4WywdEfYbbd62yuvqLjAZuPsNyvzCNV85JekAEMbKHWSHLF9h3j45SxewXDcLv328B1SEZrxg4iwGfmdt1pDFjZiTkGiFqGa/*");
+        assert_eq!(master_dsk.as_public().as_string(), "[d1d04177]tpubD6NzVbkrYhZ4WywdEfYbbd62yuvqLjAZuPsNyvzCNV85JekAEMbKHWSHLF9h3j45SxewXDcLv328B1SEZrxg4iwGfmdt1pDFjZiTkGiFqGa/*");
     }
 
     #[test]
@@ -325,7 +632,7 @@ mod test {
         assert_eq!(extended_dsk.as_string(), "tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/0/*");
         let master_dpk: &DescriptorPublicKey = &master_dsk.as_public();
         let extended_dpk: &DescriptorPublicKey = &extend_dpk(master_dpk, "m/0").unwrap();
-        assert_eq!(extended_dpk.as_string(), "tpubD6NzVbkrYhZ4WywdEfYbbd62yuvqLjAZuPsNyvzCNV85JekAEMbKHWSHLF9h3j45SxewXDcLv328B1SEZrxg4iwGfmdt1pDFjZiTkGiFqGa/0/*");
+        assert_eq!(extended_dpk.as_string(), "[d1d04177]tpubD6NzVbkrYhZ4WywdEfYbbd62yuvqLjAZuPsNyvzCNV85JekAEMbKHWSHLF9h3j45SxewXDcLv328B1SEZrxg4iwGfmdt1pDFjZiTkGiFqGa/0/*");
         let wif = "L2wTu6hQrnDMiFNWA5na6jB12ErGQqtXwqpSL7aWquJaZG8Ai3ch";
         let extended_key = DescriptorSecretKey::from_string(wif.to_string()).unwrap();
         let result = extended_key.derive(Arc::new(DerivationPath::new("m/0".to_string()).unwrap()));
@@ -357,11 +664,37 @@ mod test {
         assert_eq!(extended_dsk.as_string(), "[d1d04177/0]tprv8d7Y4JLmD25jkKbyDZXcdoPHu1YtMHuH21qeN7mFpjfumtSU7eZimFYUCSa3MYzkEYfSNRBV34GEr2QXwZCMYRZ7M1g6PUtiLhbJhBZEGYJ/0/*");
     }
 
+    #[test]
+    fn test_as_public_origin() {
+        // the master key gets its own fingerprint as origin
+        let master_dpk = get_descriptor_secret_key().as_public();
+        assert!(master_dpk.as_string().starts_with("[d1d04177]tpub"));
+
+        // an existing origin is kept
+        let derived_dpk = derive_dsk(&get_descriptor_secret_key(), "m/84h/1h/0h")
+            .unwrap()
+            .as_public();
+        assert_eq!(derived_dpk.as_string(), "[d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/*");
+
+        // the master fingerprint of a key which isn't a master key is unknown
+        let account_key = DescriptorSecretKey::from_string("tprv8d7Y4JLmD25jkKbyDZXcdoPHu1YtMHuH21qeN7mFpjfumtSU7eZimFYUCSa3MYzkEYfSNRBV34GEr2QXwZCMYRZ7M1g6PUtiLhbJhBZEGYJ/*".to_string()).unwrap();
+        assert_eq!(account_key.as_public().as_string(), "tpubD9oaCiP1MPmQdndm7DCD3D3QU34pWd6BbKSRedoZF1UJcNhEk3PJwkALNYkhxeTKL29oGNR7psqvT1KZydCGqUDEKXN6dVQJY2R8ooLPy8m/*");
+    }
+
     #[test]
     fn test_derive_hardened_path_using_public() {
         let master_dpk = get_descriptor_secret_key().as_public();
         let derived_dpk = &derive_dpk(&master_dpk, "m/84h/1h/0h");
-        assert!(derived_dpk.is_err());
+        match derived_dpk {
+            Err(BdkError::Generic {
+                error_message: message,
+            }) => {
+                assert_eq!(message, "Cannot derive a hardened child from a public key")
+            }
+            _ => panic!("expected a generic error"),
+        }
+        // an unhardened path following a hardened one is rejected too
+        assert!(derive_dpk(&master_dpk, "m/0/1h").is_err());
     }
 
     #[test]
@@ -373,4 +706,138 @@ mod test {
             "e93315d6ce401eb4db803a56232f0ed3e69b053774e6047df54f1bd00e5ea936"
         )
     }
+
+    #[test]
+    fn test_mnemonic_from_entropy_with_count() {
+        let mnemonic = Mnemonic::from_entropy_with_count(WordCount::Words12, vec![0; 16]).unwrap();
+        assert_eq!(
+            mnemonic.as_string(),
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about"
+        );
+        let mnemonic = Mnemonic::from_entropy_with_count(WordCount::Words24, vec![0; 32]).unwrap();
+        assert_eq!(mnemonic.as_string().split(' ').count(), 24);
+
+        // 16 bytes of entropy only produce 12 words
+        let result = Mnemonic::from_entropy_with_count(WordCount::Words24, vec![0; 16]);
+        assert!(matches!(result, Err(BdkError::Generic { .. })));
+    }
+
+    #[test]
+    fn test_mnemonic_to_seed() {
+        // BIP39 test vector
+        let mnemonic = Mnemonic::from_string("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about".to_string()).unwrap();
+        assert_eq!(
+            mnemonic.to_seed(Some("TREZOR".to_string())).to_hex(),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        assert_eq!(
+            mnemonic.to_seed(None),
+            mnemonic.to_seed(Some("".to_string()))
+        );
+        // the passphrase is normalized
+        assert_eq!(
+            mnemonic.to_seed(Some("caf\u{e9}".to_string())),
+            mnemonic.to_seed(Some("cafe\u{301}".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_with_origin() {
+        let master_dpk = get_descriptor_secret_key().as_public();
+        let path = Arc::new(DerivationPath::new("m/84h/1h/0h".to_string()).unwrap());
+        let dpk = master_dpk
+            .with_origin("d1d04177".to_string(), path.clone())
+            .unwrap();
+        assert_eq!(dpk.as_string(), "[d1d04177/84'/1'/0']tpubD6NzVbkrYhZ4WywdEfYbbd62yuvqLjAZuPsNyvzCNV85JekAEMbKHWSHLF9h3j45SxewXDcLv328B1SEZrxg4iwGfmdt1pDFjZiTkGiFqGa/*");
+
+        assert!(master_dpk
+            .with_origin("d1d0417".to_string(), path.clone())
+            .is_err());
+        assert!(master_dpk
+            .with_origin("d1d0417g".to_string(), path)
+            .is_err());
+    }
+
+    #[test]
+    fn test_descriptor_public_key_address() {
+        // BIP-84 test vector, account 0 of "abandon abandon ... about"
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*";
+        let public_key = DescriptorPublicKey::from_string(xpub.to_string()).unwrap();
+        let address = |index, script_type| {
+            public_key
+                .address(index, script_type, Network::Bitcoin)
+                .map(|address| address.as_string())
+        };
+        assert_eq!(
+            address(0, ScriptType::P2wpkh).unwrap(),
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu"
+        );
+        assert_eq!(
+            address(1, ScriptType::P2wpkh).unwrap(),
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+        );
+        assert!(address(0, ScriptType::P2pkh).unwrap().starts_with('1'));
+        assert!(address(0, ScriptType::P2shP2wpkh).unwrap().starts_with('3'));
+        assert!(address(0, ScriptType::P2tr).unwrap().starts_with("bc1p"));
+        assert!(matches!(
+            address(0, ScriptType::P2wsh),
+            Err(BdkError::Generic { .. })
+        ));
+    }
+
+    #[test]
+    fn test_descriptor_public_key_at_derivation_index() {
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*";
+        let public_key = DescriptorPublicKey::from_string(xpub.to_string()).unwrap();
+        let derived_key = public_key.at_derivation_index(1).unwrap();
+        assert_eq!(derived_key.as_string(), xpub.replace("/*", "/1"));
+        assert_eq!(
+            derived_key
+                .address(0, ScriptType::P2wpkh, Network::Bitcoin)
+                .unwrap()
+                .as_string(),
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g"
+        );
+
+        assert!(matches!(
+            public_key.at_derivation_index(1 << 31),
+            Err(BdkError::Generic { .. })
+        ));
+        let hardened_wildcard =
+            DescriptorPublicKey::from_string(xpub.replace("/*", "/*'")).unwrap();
+        assert!(matches!(
+            hardened_wildcard.at_derivation_index(1),
+            Err(BdkError::Generic { .. })
+        ));
+    }
+
+    #[test]
+    fn test_expand_multipath_public_key() {
+        let public_keys = expand_multipath_public_key("[d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/<0;1>/*".to_string()).unwrap();
+        assert_eq!(public_keys.len(), 2);
+        assert_eq!(public_keys[0].as_string(), "[d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*");
+        assert_eq!(public_keys[1].as_string(), "[d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/1/*");
+
+        let single_path = expand_multipath_public_key("[d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*".to_string());
+        assert!(matches!(single_path, Err(BdkError::Generic { .. })));
+    }
+
+    #[test]
+    fn test_slip132_descriptor_public_key() {
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs/0/*";
+        let public_key = DescriptorPublicKey::from_string(zpub.to_string()).unwrap();
+        assert_eq!(public_key.as_string(), "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*");
+        assert_eq!(public_key.script_type(), Some(ScriptType::P2wpkh));
+
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+        let public_key = DescriptorPublicKey::from_string(xpub.to_string()).unwrap();
+        assert_eq!(public_key.script_type(), None);
+
+        // an extended private key is not a valid extended public key prefix
+        let tprv = "tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc";
+        let result = DescriptorPublicKey::from_string(tprv.to_string());
+        assert!(
+            matches!(result, Err(BdkError::Generic { error_message: message }) if message == "Unrecognized extended public key prefix: tprv")
+        );
+    }
 }
@@ -1,37 +1,56 @@
 mod blockchain;
 mod database;
 mod descriptor;
+mod error;
 mod keys;
 mod psbt;
 mod wallet;
 
 use crate::blockchain::{
-    Auth, Blockchain, BlockchainConfig, ElectrumConfig, EsploraConfig, RpcConfig, RpcSyncParams,
+    default_esplora_url, Auth, Blockchain, BlockchainConfig, BroadcastResult, ElectrumConfig,
+    EsploraConfig, FeeEstimate, MerkleProof, RpcConfig, RpcSyncParams,
 };
 use crate::database::DatabaseConfig;
-use crate::descriptor::Descriptor;
+use crate::descriptor::{new_multipath_descriptor, Descriptor};
+use crate::error::BdkError;
 use crate::keys::DerivationPath;
-use crate::keys::{DescriptorPublicKey, DescriptorSecretKey, Mnemonic};
-use crate::psbt::PartiallySignedTransaction;
-use crate::wallet::SignOptions;
+use crate::keys::{
+    expand_multipath_public_key, DescriptorPublicKey, DescriptorSecretKey, Mnemonic,
+};
+use crate::psbt::{ecdsa_sighash, PartiallySignedTransaction};
 use crate::wallet::{BumpFeeTxBuilder, TxBuilder, Wallet};
-use bdk::bitcoin::blockdata::script::Script as BdkScript;
+use crate::wallet::{
+    ImmatureUtxo, PsbtOutputInfo, SignOptions, SignerInfo, SyncReport, TransactionFilter,
+    TxInputInfo, TxOutputInfo,
+};
+use bdk::bitcoin::blockdata::script::{Instruction, Script as BdkScript};
 use bdk::bitcoin::blockdata::transaction::TxIn as BdkTxIn;
 use bdk::bitcoin::blockdata::transaction::TxOut as BdkTxOut;
 use bdk::bitcoin::consensus::Decodable;
 use bdk::bitcoin::psbt::serialize::Serialize;
+use bdk::bitcoin::secp256k1::ecdsa::Signature as EcdsaSignature;
+use bdk::bitcoin::secp256k1::{All, Message, Secp256k1};
 use bdk::bitcoin::util::address::{Payload as BdkPayload, WitnessVersion};
+use bdk::bitcoin::util::amount::{Amount, Denomination};
+use bdk::bitcoin::util::bip32::Fingerprint;
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction as BdkPartiallySignedTransaction;
 use bdk::bitcoin::{
-    Address as BdkAddress, Network, OutPoint as BdkOutPoint, Transaction as BdkTransaction, Txid,
+    Address as BdkAddress, EcdsaSig, EcdsaSighashType, Network, OutPoint as BdkOutPoint,
+    PublicKey as BdkPublicKey, Transaction as BdkTransaction, Txid,
 };
 use bdk::blockchain::Progress as BdkProgress;
 use bdk::database::any::{SledDbConfiguration, SqliteDbConfiguration};
 use bdk::keys::bip39::WordCount;
+use bdk::signer::{InputSigner, SignerCommon, SignerError, SignerId};
 use bdk::wallet::AddressIndex as BdkAddressIndex;
 use bdk::wallet::AddressInfo as BdkAddressInfo;
 use bdk::LocalUtxo as BdkLocalUtxo;
 use bdk::TransactionDetails as BdkTransactionDetails;
-use bdk::{Balance as BdkBalance, BlockTime, Error as BdkError, FeeRate, KeychainKind};
+use bdk::{
+    Balance as BdkBalance, BlockTime, FeeRate as BdkFeeRate, KeychainKind,
+    SignOptions as BdkSignOptions,
+};
+use log::{Level, Log, Metadata, Record};
 use std::convert::From;
 use std::fmt;
 use std::fmt::Debug;
@@ -47,6 +66,14 @@ pub struct ScriptAmount {
     pub amount: u64,
 }
 
+/// The external and internal descriptors of a wallet.
+pub struct DescriptorPair {
+    /// Descriptor used for receiving addresses.
+    pub external: Arc<Descriptor>,
+    /// Descriptor used for change addresses.
+    pub internal: Arc<Descriptor>,
+}
+
 /// A derived address and the index it was found at.
 pub struct AddressInfo {
     /// Child index of this address.
@@ -105,6 +132,23 @@ impl From<AddressIndex> for BdkAddressIndex {
     }
 }
 
+/// The type of output script, e.g. as implied by SLIP-0132 extended key versions (ypub, zpub, ...).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptType {
+    /// Legacy pay to public key hash.
+    P2pkh,
+    /// Pay to witness public key hash nested in pay to script hash.
+    P2shP2wpkh,
+    /// Native segwit pay to witness public key hash.
+    P2wpkh,
+    /// Pay to witness script hash nested in pay to script hash.
+    P2shP2wsh,
+    /// Native segwit pay to witness script hash.
+    P2wsh,
+    /// Taproot pay to key path.
+    P2tr,
+}
+
 /// A wallet transaction
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct TransactionDetails {
@@ -117,32 +161,69 @@ pub struct TransactionDetails {
     /// Sent value (sats)
     /// Sum of owned inputs of this transaction.
     pub sent: u64,
+    /// Net effect of this transaction on the wallet balance (sats), received minus sent. It is
+    /// negative for outgoing transactions, and the fee paid by the wallet is already included as
+    /// it is part of sent.
+    pub net: i64,
     /// Fee value (sats) if confirmed.
     /// The availability of the fee depends on the backend. It's never None with an Electrum
     /// Server backend, but it could be None with a Bitcoin RPC node without txindex that receive
     /// funds while offline.
     pub fee: Option<u64>,
+    /// Fee rate in sat/vB, from the fee and the size of the transaction. None when the fee is
+    /// unknown, see fee.
+    pub fee_rate: Option<f32>,
     /// If the transaction is confirmed, contains height and timestamp of the block containing the
     /// transaction, unconfirmed transaction contains `None`.
     pub confirmation_time: Option<BlockTime>,
+    /// Height of the block containing the transaction, the same as confirmation_time.height but
+    /// easier to compare and sort by.
+    pub confirmation_height: Option<u32>,
+    /// Timestamp of the block containing the transaction, the same as
+    /// confirmation_time.timestamp.
+    pub confirmation_timestamp: Option<u64>,
+    /// Ids of the other wallet transactions spending some of the same outputs, e.g. the
+    /// replacement of a transaction bumped with RBF. At most one of conflicting transactions can
+    /// confirm, so the unconfirmed ones can be hidden once one of them is confirmed.
+    pub conflicts: Vec<String>,
 }
 
 impl From<BdkTransactionDetails> for TransactionDetails {
     fn from(tx_details: BdkTransactionDetails) -> Self {
+        let fee_rate = fee_rate(&tx_details);
         let optional_tx: Option<Arc<Transaction>> =
             tx_details.transaction.map(|tx| Arc::new(tx.into()));
 
         TransactionDetails {
             transaction: optional_tx,
             fee: tx_details.fee,
+            fee_rate,
             txid: tx_details.txid.to_string(),
             received: tx_details.received,
             sent: tx_details.sent,
+            net: tx_details.received as i64 - tx_details.sent as i64,
+            confirmation_height: tx_details
+                .confirmation_time
+                .as_ref()
+                .map(|block_time| block_time.height),
+            confirmation_timestamp: tx_details
+                .confirmation_time
+                .as_ref()
+                .map(|block_time| block_time.timestamp),
             confirmation_time: tx_details.confirmation_time,
+            conflicts: Vec::new(),
         }
     }
 }
 
+/// The fee rate of a wallet transaction in sat/vB, if both its fee and raw transaction are known.
+fn fee_rate(tx_details: &BdkTransactionDetails) -> Option<f32> {
+    match (&tx_details.transaction, tx_details.fee) {
+        (Some(tx), Some(fee)) => Some(BdkFeeRate::from_vb(fee, tx.vsize()).as_sat_per_vb()),
+        _ => None,
+    }
+}
+
 /// A reference to a transaction output.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct OutPoint {
@@ -161,6 +242,23 @@ impl From<&OutPoint> for BdkOutPoint {
     }
 }
 
+impl From<&BdkOutPoint> for OutPoint {
+    fn from(outpoint: &BdkOutPoint) -> Self {
+        OutPoint {
+            txid: outpoint.txid.to_string(),
+            vout: outpoint.vout,
+        }
+    }
+}
+
+/// Parse an outpoint in the `txid:vout` form used by block explorers and Bitcoin Core.
+fn outpoint_from_string(outpoint: String) -> Result<OutPoint, BdkError> {
+    let outpoint = BdkOutPoint::from_str(&outpoint).map_err(|e| BdkError::Generic {
+        error_message: e.to_string(),
+    })?;
+    Ok(OutPoint::from(&outpoint))
+}
+
 pub struct Balance {
     // All coinbase outputs not yet matured
     pub immature: u64,
@@ -214,6 +312,9 @@ pub struct LocalUtxo {
     txout: TxOut,
     keychain: KeychainKind,
     is_spent: bool,
+    /// Height of the block that confirmed the transaction creating the output, None while it is
+    /// unconfirmed, e.g. to gray out unconfirmed coins in a coin control UI.
+    confirmation_height: Option<u32>,
 }
 
 impl From<BdkLocalUtxo> for LocalUtxo {
@@ -231,11 +332,13 @@ impl From<BdkLocalUtxo> for LocalUtxo {
             },
             keychain: local_utxo.keychain,
             is_spent: local_utxo.is_spent,
+            // only known to the wallet, which looks up the transaction
+            confirmation_height: None,
         }
     }
 }
 
-/// Trait that logs at level INFO every update received (if any).
+/// Receives the progress updates of a wallet sync, see Logger to receive log records.
 pub trait Progress: Send + Sync + 'static {
     /// Send a new progress update. The progress value should be in the range 0.0 - 100.0, and the message value is an
     /// optional text message that can be displayed to the user.
@@ -247,7 +350,7 @@ struct ProgressHolder {
 }
 
 impl BdkProgress for ProgressHolder {
-    fn update(&self, progress: f32, message: Option<String>) -> Result<(), BdkError> {
+    fn update(&self, progress: f32, message: Option<String>) -> Result<(), bdk::Error> {
         self.progress.update(progress, message);
         Ok(())
     }
@@ -259,6 +362,170 @@ impl Debug for ProgressHolder {
     }
 }
 
+/// An external signer, e.g. a hardware wallet or a remote HSM, holding one of the wallet's private
+/// keys.
+pub trait Signer: Send + Sync + 'static {
+    /// Sign the 32 byte ECDSA sighash of a transaction input with the key identified by its public
+    /// key (hex) and its derivation path from the signer's master key. Returns the DER encoded
+    /// signature, or None to leave the input unsigned.
+    fn sign(
+        &self,
+        sighash: Vec<u8>,
+        public_key: String,
+        derivation_path: String,
+    ) -> Option<Vec<u8>>;
+}
+
+struct SignerHolder {
+    fingerprint: Fingerprint,
+    signer: Box<dyn Signer>,
+}
+
+impl SignerCommon for SignerHolder {
+    fn id(&self, _secp: &Secp256k1<All>) -> SignerId {
+        SignerId::Fingerprint(self.fingerprint)
+    }
+}
+
+impl InputSigner for SignerHolder {
+    fn sign_input(
+        &self,
+        psbt: &mut BdkPartiallySignedTransaction,
+        input_index: usize,
+        sign_options: &BdkSignOptions,
+        secp: &Secp256k1<All>,
+    ) -> Result<(), SignerError> {
+        let input = psbt
+            .inputs
+            .get(input_index)
+            .ok_or(SignerError::InputIndexOutOfRange)?;
+        if input.final_script_sig.is_some() || input.final_script_witness.is_some() {
+            return Ok(());
+        }
+        // the signer's keys which haven't signed the input yet
+        let keys: Vec<(BdkPublicKey, String)> = input
+            .bip32_derivation
+            .iter()
+            .filter(|(_, (fingerprint, _))| *fingerprint == self.fingerprint)
+            .map(|(public_key, (_, path))| (BdkPublicKey::new(*public_key), path.to_string()))
+            .filter(|(public_key, _)| !input.partial_sigs.contains_key(public_key))
+            .collect();
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let (sighash, sighash_type) = ecdsa_sighash(psbt, input_index)?;
+        if sighash_type != EcdsaSighashType::All && !sign_options.allow_all_sighashes {
+            return Err(SignerError::NonStandardSighash);
+        }
+        let message = Message::from_slice(&sighash[..]).map_err(|_| SignerError::InvalidSighash)?;
+        for (public_key, derivation_path) in keys {
+            let signature = self.signer.sign(
+                sighash[..].to_vec(),
+                public_key.to_string(),
+                derivation_path,
+            );
+            if let Some(signature) = signature {
+                // reject signatures which aren't made with the expected key
+                let sig = EcdsaSignature::from_der(&signature)
+                    .ok()
+                    .filter(|sig| secp.verify_ecdsa(&message, sig, &public_key.inner).is_ok())
+                    .ok_or(SignerError::InvalidKey)?;
+                psbt.inputs[input_index].partial_sigs.insert(
+                    public_key,
+                    EcdsaSig {
+                        sig,
+                        hash_ty: sighash_type,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Debug for SignerHolder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SignerHolder")
+            .field("fingerprint", &self.fingerprint)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The level of a log record, from the most to the least severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => LogLevel::Error,
+            Level::Warn => LogLevel::Warn,
+            Level::Info => LogLevel::Info,
+            Level::Debug => LogLevel::Debug,
+            Level::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => Level::Error,
+            LogLevel::Warn => Level::Warn,
+            LogLevel::Info => Level::Info,
+            LogLevel::Debug => Level::Debug,
+            LogLevel::Trace => Level::Trace,
+        }
+    }
+}
+
+/// Receives the log records of bdk and bdk-ffi (e.g. wallet syncs and broadcasts), so apps can
+/// route them to their own logging system such as os_log or logcat.
+pub trait Logger: Send + Sync + 'static {
+    /// Log a message. The message starts with the module it comes from.
+    fn log(&self, level: LogLevel, message: String);
+}
+
+struct LoggerHolder {
+    logger: Box<dyn Logger>,
+}
+
+impl Log for LoggerHolder {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.logger.log(
+                record.level().into(),
+                format!("{}: {}", record.target(), record.args()),
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Send the log records up to `max_level` to the given logger. Nothing is logged unless a logger
+/// is set, and it can only be set once per process.
+fn set_logger(logger: Box<dyn Logger>, max_level: LogLevel) -> Result<(), BdkError> {
+    log::set_logger(Box::leak(Box::new(LoggerHolder { logger }))).map_err(|e| {
+        BdkError::Generic {
+            error_message: e.to_string(),
+        }
+    })?;
+    log::set_max_level(Level::from(max_level).to_level_filter());
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub struct TxIn {
     pub previous_output: OutPoint,
@@ -300,6 +567,8 @@ impl Transaction {
         self.internal.txid().to_string()
     }
 
+    /// The weight in weight units as defined by BIP141, what fee calculations consistent with
+    /// Bitcoin Core should be based on.
     fn weight(&self) -> u64 {
         self.internal.weight() as u64
     }
@@ -308,6 +577,8 @@ impl Transaction {
         self.internal.size() as u64
     }
 
+    /// The virtual size in vbytes, which is ceil(weight / 4) so it is rounded up for weights that
+    /// aren't a multiple of 4.
     fn vsize(&self) -> u64 {
         self.internal.vsize() as u64
     }
@@ -320,6 +591,8 @@ impl Transaction {
         self.internal.is_coin_base()
     }
 
+    /// Whether the transaction signals replace-by-fee (BIP 125), i.e. any of its inputs has an
+    /// nSequence below 0xFFFFFFFE. Only such transactions can be fee bumped with BumpFeeTxBuilder.
     fn is_explicitly_rbf(&self) -> bool {
         self.internal.is_explicitly_rbf()
     }
@@ -332,6 +605,8 @@ impl Transaction {
         self.internal.version
     }
 
+    /// The nLockTime of the transaction, a block height below 500,000,000 or a unix timestamp
+    /// otherwise. The transaction can only be mined after it, if is_lock_time_enabled.
     fn lock_time(&self) -> u32 {
         self.internal.lock_time.0
     }
@@ -361,14 +636,18 @@ impl Address {
     fn new(address: String) -> Result<Self, BdkError> {
         BdkAddress::from_str(address.as_str())
             .map(|a| Address { address: a })
-            .map_err(|e| BdkError::Generic(e.to_string()))
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })
     }
 
     /// alternative constructor
     fn from_script(script: Arc<Script>, network: Network) -> Result<Self, BdkError> {
         BdkAddress::from_script(&script.script, network)
             .map(|a| Address { address: a })
-            .map_err(|e| BdkError::Generic(e.to_string()))
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })
     }
 
     fn payload(&self) -> Payload {
@@ -386,6 +665,25 @@ impl Address {
         }
     }
 
+    /// The bare payload bytes of the address, without the script opcodes: the hash of P2PKH and
+    /// P2SH addresses, and the witness program of segwit addresses, e.g. the 20 byte hash of a
+    /// P2WPKH address or the 32 byte key of a P2TR address.
+    fn payload_bytes(&self) -> Vec<u8> {
+        match self.payload() {
+            Payload::PubkeyHash { pubkey_hash } => pubkey_hash,
+            Payload::ScriptHash { script_hash } => script_hash,
+            Payload::WitnessProgram { program, .. } => program,
+        }
+    }
+
+    /// The witness version of segwit addresses, None for P2PKH and P2SH addresses.
+    fn witness_version(&self) -> Option<WitnessVersion> {
+        match self.address.payload {
+            BdkPayload::WitnessProgram { version, .. } => Some(version),
+            _ => None,
+        }
+    }
+
     fn network(&self) -> Network {
         self.address.network
     }
@@ -396,8 +694,32 @@ impl Address {
         })
     }
 
-    fn to_qr_uri(&self) -> String {
-        self.address.to_qr_uri()
+    /// A BIP21 URI to encode in a QR code, e.g. `BITCOIN:TB1Q...?amount=0.001&label=Alice`. Bech32
+    /// addresses are uppercased so the QR code can use the compact alphanumeric mode. The amount
+    /// is in bitcoin without trailing zeros, and the label and message are percent-encoded.
+    fn to_qr_uri(
+        &self,
+        amount_sats: Option<u64>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> String {
+        let mut params = Vec::new();
+        if let Some(amount_sats) = amount_sats {
+            let amount = Amount::from_sat(amount_sats).to_string_in(Denomination::Bitcoin);
+            params.push(format!("amount={}", amount));
+        }
+        if let Some(label) = label {
+            params.push(format!("label={}", percent_encode(&label)));
+        }
+        if let Some(message) = message {
+            params.push(format!("message={}", percent_encode(&message)));
+        }
+        let uri = self.address.to_qr_uri();
+        if params.is_empty() {
+            uri
+        } else {
+            format!("{}?{}", uri, params.join("&"))
+        }
     }
 
     fn as_string(&self) -> String {
@@ -405,6 +727,20 @@ impl Address {
     }
 }
 
+/// Percent-encode everything but the unreserved characters of RFC 3986, for BIP21 URI values.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
 impl From<BdkAddress> for Address {
     fn from(address: BdkAddress) -> Self {
         Address { address }
@@ -427,6 +763,9 @@ pub enum Payload {
     },
 }
 
+/// The maximum size of a script, larger scripts can't be spent.
+const MAX_SCRIPT_SIZE: usize = 10_000;
+
 /// A Bitcoin script.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Script {
@@ -438,6 +777,56 @@ impl Script {
         let script: BdkScript = BdkScript::from(raw_output_script);
         Script { script }
     }
+
+    /// Like Script.new, but checks that the script is a plausible output script: not empty, no
+    /// larger than the 10,000 bytes consensus limit and without truncated data pushes.
+    fn from_bytes_checked(raw_output_script: Vec<u8>) -> Result<Self, BdkError> {
+        if raw_output_script.is_empty() {
+            return Err(BdkError::Generic {
+                error_message: "The script is empty".to_string(),
+            });
+        }
+        if raw_output_script.len() > MAX_SCRIPT_SIZE {
+            return Err(BdkError::Generic {
+                error_message: format!("The script is larger than {} bytes", MAX_SCRIPT_SIZE),
+            });
+        }
+        let script = Script::new(raw_output_script);
+        if let Some(Err(e)) = script.script.instructions().find(|i| i.is_err()) {
+            return Err(BdkError::Generic {
+                error_message: format!("Invalid script: {}", e),
+            });
+        }
+        Ok(script)
+    }
+
+    /// The address of this script on the given network, the inverse of Address.script_pubkey.
+    /// Returns an error instead of panicking for scripts without an address form (e.g. OP_RETURN).
+    fn to_address(&self, network: Network) -> Result<Arc<Address>, BdkError> {
+        Address::from_script(Arc::new(self.clone()), network).map(Arc::new)
+    }
+
+    /// Whether this is an unspendable OP_RETURN output script, used to embed data.
+    fn is_op_return(&self) -> bool {
+        self.script.is_op_return()
+    }
+
+    /// The data pushed after the OP_RETURN opcode, e.g. as added with TxBuilder.add_data, with
+    /// multiple pushes concatenated. Returns None if this isn't an OP_RETURN output script or
+    /// it contains anything but data pushes after the OP_RETURN opcode.
+    fn op_return_data(&self) -> Option<Vec<u8>> {
+        if !self.script.is_op_return() {
+            return None;
+        }
+        let mut data = Vec::new();
+        for instruction in self.script.instructions().skip(1) {
+            match instruction {
+                Ok(Instruction::PushBytes(bytes)) => data.extend_from_slice(bytes),
+                _ => return None,
+            }
+        }
+        Some(data)
+    }
 }
 
 impl From<BdkScript> for Script {
@@ -446,17 +835,92 @@ impl From<BdkScript> for Script {
     }
 }
 
+/// Fee rate, in satoshi per virtual byte.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FeeRate {
+    internal: BdkFeeRate,
+}
+
+impl FeeRate {
+    fn from_sat_per_vb(sat_per_vb: f32) -> Self {
+        FeeRate {
+            internal: BdkFeeRate::from_sat_per_vb(sat_per_vb),
+        }
+    }
+
+    /// The default minimum relay fee rate of Bitcoin Core (1 sat/vB). Backends can fail to
+    /// estimate a fee rate (e.g. on regtest or a fresh node), this is a sane floor to fall back to
+    /// and to clamp user entered fee rates to.
+    fn default_min_relay() -> Self {
+        FeeRate {
+            internal: BdkFeeRate::default_min_relay_fee(),
+        }
+    }
+
+    fn as_sat_per_vb(&self) -> f32 {
+        self.internal.as_sat_per_vb()
+    }
+
+    /// The fee rate in satoshi per 1000 weight units, rounded up so the rate is always met. A
+    /// virtual byte is 4 weight units, so 1 sat/vB is 250 sat/kwu.
+    fn as_sat_per_kwu(&self) -> u64 {
+        ceil_fee(f64::from(self.internal.as_sat_per_vb()) * 250.0) as u64
+    }
+
+    /// The absolute fee in satoshi of a transaction of the given virtual size at this fee rate,
+    /// rounded up so the rate is always met. Returns None if it overflows.
+    fn checked_mul_vsize(&self, vsize: u64) -> Option<u64> {
+        let fee = ceil_fee(f64::from(self.internal.as_sat_per_vb()) * vsize as f64);
+        // u64::MAX rounds up to 2^64 as a float
+        if fee < u64::MAX as f64 {
+            Some(fee as u64)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this fee rate is greater than the other fee rate.
+    fn gt(&self, other: &FeeRate) -> bool {
+        self.internal > other.internal
+    }
+
+    /// Whether this fee rate is less than the other fee rate.
+    fn lt(&self, other: &FeeRate) -> bool {
+        self.internal < other.internal
+    }
+}
+
+/// Round up an amount computed from a fee rate, ignoring the error of the rate being stored as a
+/// float: 1.1 sat/vB is stored as slightly more than 1.1, which must not round 110 sats up to 111.
+fn ceil_fee(amount: f64) -> f64 {
+    let nearest = amount.round();
+    if (amount - nearest).abs() <= amount * f64::from(f32::EPSILON) {
+        nearest
+    } else {
+        amount.ceil()
+    }
+}
+
+impl From<BdkFeeRate> for FeeRate {
+    fn from(fee_rate: BdkFeeRate) -> Self {
+        FeeRate { internal: fee_rate }
+    }
+}
+
 #[derive(Clone, Debug)]
 enum RbfValue {
     Default,
     Value(u32),
 }
 
-/// The result after calling the TxBuilder finish() function. Contains unsigned PSBT and
-/// transaction details.
+/// The result after calling the TxBuilder finish() function. Contains unsigned PSBT, transaction
+/// details and the position of the change output, if any.
 pub struct TxBuilderResult {
     pub(crate) psbt: Arc<PartiallySignedTransaction>,
     pub transaction_details: TransactionDetails,
+    /// Index of the change output in the transaction, None if there is no change output or the
+    /// excess was sent with drain_to.
+    pub change_output_index: Option<u32>,
 }
 
 uniffi::deps::static_assertions::assert_impl_all!(Wallet: Sync, Send);
@@ -468,10 +932,17 @@ uniffi::deps::static_assertions::assert_impl_all!(Wallet: Sync, Send);
 mod test {
     use super::Transaction;
     use crate::Network::Regtest;
-    use crate::{Address, Payload};
+    use crate::{
+        outpoint_from_string, set_logger, Address, BdkError, FeeRate, LogLevel, Logger, OutPoint,
+        Payload, Script,
+    };
     use assert_matches::assert_matches;
     use bdk::bitcoin::hashes::hex::FromHex;
     use bdk::bitcoin::util::address::WitnessVersion;
+    use bdk::bitcoin::{
+        PackedLockTime, Sequence, Transaction as BdkTransaction, TxIn, TxOut, Witness,
+    };
+    use std::sync::{Arc, Mutex};
 
     // Verify that bdk-ffi Transaction can be created from valid bytes and serialized back into the same bytes.
     #[test]
@@ -482,6 +953,84 @@ mod test {
         assert_eq!(test_tx_bytes, serialized_tx_to_bytes);
     }
 
+    // Verify that the vsize of a Transaction is its weight divided by 4 and rounded up.
+    #[test]
+    fn test_transaction_weight_vsize() {
+        let tx: Transaction = BdkTransaction {
+            version: 2,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                witness: Witness::from_vec(vec![vec![0]]),
+                ..Default::default()
+            }],
+            output: vec![TxOut::default()],
+        }
+        .into();
+        // 60 bytes without the witness and 65 bytes with it
+        assert_eq!(tx.weight(), 60 * 3 + 65);
+        assert_eq!(tx.vsize(), 62);
+    }
+
+    struct TestLogger {
+        records: Arc<Mutex<Vec<(LogLevel, String)>>>,
+    }
+
+    impl Logger for TestLogger {
+        fn log(&self, level: LogLevel, message: String) {
+            self.records.lock().unwrap().push((level, message));
+        }
+    }
+
+    // Verify that log records up to the max level are sent to the logger, which is only set once.
+    #[test]
+    fn test_set_logger() {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let logger = TestLogger {
+            records: records.clone(),
+        };
+        set_logger(Box::new(logger), LogLevel::Info).unwrap();
+
+        log::info!("test info record");
+        log::debug!("test debug record");
+        let records = records.lock().unwrap().clone();
+        assert!(records.contains(&(LogLevel::Info, "bdkffi::test: test info record".to_string())));
+        assert!(!records
+            .iter()
+            .any(|(_, message)| message.contains("test debug record")));
+
+        let other_logger = TestLogger {
+            records: Arc::new(Mutex::new(Vec::new())),
+        };
+        assert_matches!(
+            set_logger(Box::new(other_logger), LogLevel::Trace),
+            Err(BdkError::Generic { .. })
+        );
+    }
+
+    // Verify that bdk-ffi Transaction.is_explicitly_rbf checks the nSequence of all the inputs.
+    #[test]
+    fn test_transaction_is_explicitly_rbf() {
+        let tx_with_sequences = |sequences: &[u32]| -> Transaction {
+            BdkTransaction {
+                version: 2,
+                lock_time: PackedLockTime(0),
+                input: sequences
+                    .iter()
+                    .map(|sequence| TxIn {
+                        sequence: Sequence(*sequence),
+                        ..Default::default()
+                    })
+                    .collect(),
+                output: vec![],
+            }
+            .into()
+        };
+        assert!(!tx_with_sequences(&[0xFFFFFFFF]).is_explicitly_rbf());
+        assert!(!tx_with_sequences(&[0xFFFFFFFE, 0xFFFFFFFF]).is_explicitly_rbf());
+        assert!(tx_with_sequences(&[0xFFFFFFFD]).is_explicitly_rbf());
+        assert!(tx_with_sequences(&[0xFFFFFFFF, 0]).is_explicitly_rbf());
+    }
+
     // Verify that bdk-ffi Address.payload includes expected WitnessProgram variant, version and program bytes.
     #[test]
     fn test_address_witness_program() {
@@ -494,4 +1043,161 @@ mod test {
         });
         assert_eq!(address.network(), Regtest);
     }
+
+    // Verify that bdk-ffi Address.payload_bytes and witness_version match the payload.
+    #[test]
+    fn test_address_payload_bytes() {
+        let address =
+            Address::new("bcrt1qqjn9gky9mkrm3c28e5e87t5akd3twg6xezp0tv".to_string()).unwrap();
+        assert_eq!(
+            address.payload_bytes(),
+            Vec::from_hex("04a6545885dd87b8e147cd327f2e9db362b72346").unwrap()
+        );
+        assert_eq!(address.witness_version(), Some(WitnessVersion::V0));
+
+        let address = Address::new("mkHS9ne12qx9pS9VojpwU5xtRd4T7X7ZUt".to_string()).unwrap();
+        assert_eq!(address.payload_bytes().len(), 20);
+        assert_eq!(address.witness_version(), None);
+    }
+
+    // Verify that converting an Address to a Script and back with the same network round-trips.
+    #[test]
+    fn test_script_to_address() {
+        for address in [
+            "bcrt1qqjn9gky9mkrm3c28e5e87t5akd3twg6xezp0tv",
+            "mkHS9ne12qx9pS9VojpwU5xtRd4T7X7ZUt",
+            "2MzQwSSnBHWHqSAqtTVQ6v47XtaisrJa1Vc",
+        ] {
+            let address = Address::new(address.to_string()).unwrap();
+            let round_trip = address.script_pubkey().to_address(Regtest).unwrap();
+            assert_eq!(round_trip.as_string(), address.as_string());
+        }
+
+        // OP_RETURN outputs have no address
+        let op_return = Script::new(Vec::from_hex("6a0474657374").unwrap());
+        assert!(op_return.to_address(Regtest).is_err());
+    }
+
+    #[test]
+    fn test_script_op_return_data() {
+        let op_return = Script::new(Vec::from_hex("6a0474657374").unwrap());
+        assert!(op_return.is_op_return());
+        assert_eq!(op_return.op_return_data(), Some(b"test".to_vec()));
+
+        // multiple pushes are concatenated
+        let two_pushes = Script::new(Vec::from_hex("6a0274650273740474657374").unwrap());
+        assert_eq!(two_pushes.op_return_data(), Some(b"testtest".to_vec()));
+
+        let empty = Script::new(Vec::from_hex("6a").unwrap());
+        assert_eq!(empty.op_return_data(), Some(Vec::new()));
+
+        let p2wpkh = Address::new("bcrt1qqjn9gky9mkrm3c28e5e87t5akd3twg6xezp0tv".to_string())
+            .unwrap()
+            .script_pubkey();
+        assert!(!p2wpkh.is_op_return());
+        assert_eq!(p2wpkh.op_return_data(), None);
+    }
+
+    #[test]
+    fn test_fee_rate_comparison() {
+        let min_relay = FeeRate::default_min_relay();
+        assert_eq!(min_relay.as_sat_per_vb(), 1.0);
+
+        let fee_rate = FeeRate::from_sat_per_vb(2.5);
+        assert!(fee_rate.gt(&min_relay));
+        assert!(!fee_rate.lt(&min_relay));
+        assert!(min_relay.lt(&fee_rate));
+        assert!(!min_relay.gt(&FeeRate::from_sat_per_vb(1.0)));
+        assert!(!min_relay.lt(&FeeRate::from_sat_per_vb(1.0)));
+    }
+
+    #[test]
+    fn test_fee_rate_arithmetic() {
+        let min_relay = FeeRate::default_min_relay();
+        assert_eq!(min_relay.as_sat_per_kwu(), 250);
+        assert_eq!(min_relay.checked_mul_vsize(141), Some(141));
+
+        // 1.1 isn't exact as a float, the fee is still 110 sats for 100 vB
+        let fee_rate = FeeRate::from_sat_per_vb(1.1);
+        assert_eq!(fee_rate.as_sat_per_kwu(), 275);
+        assert_eq!(fee_rate.checked_mul_vsize(100), Some(110));
+        // rounded up to meet the fee rate
+        assert_eq!(fee_rate.checked_mul_vsize(141), Some(156));
+        assert_eq!(fee_rate.checked_mul_vsize(0), Some(0));
+        assert_eq!(fee_rate.checked_mul_vsize(u64::MAX), None);
+
+        // a fractional rate is rounded up, not to the nearest sat/kwu
+        let fee_rate = FeeRate::from_sat_per_vb(1.001);
+        assert_eq!(fee_rate.as_sat_per_kwu(), 251);
+        assert_eq!(fee_rate.checked_mul_vsize(1000), Some(1001));
+        assert_eq!(fee_rate.checked_mul_vsize(1001), Some(1003));
+    }
+
+    #[test]
+    fn test_outpoint_from_string() {
+        let txid = "312f1733badab22dc26b8dcbc83ba5629fb7b493af802e8abe07d865e49629c5";
+        assert_eq!(
+            outpoint_from_string(format!("{}:1", txid)).unwrap(),
+            OutPoint {
+                txid: txid.to_string(),
+                vout: 1,
+            }
+        );
+        assert_matches!(
+            outpoint_from_string(txid.to_string()),
+            Err(BdkError::Generic { .. })
+        );
+        assert_matches!(
+            outpoint_from_string(format!("{}:-1", txid)),
+            Err(BdkError::Generic { .. })
+        );
+        assert_matches!(
+            outpoint_from_string("not a txid:0".to_string()),
+            Err(BdkError::Generic { .. })
+        );
+    }
+
+    #[test]
+    fn test_script_from_bytes_checked() {
+        let p2wpkh = Vec::from_hex("0014c2e2a7e1b4ffd6e0e2a2e5c5bb4f3ae8c7b2c53c").unwrap();
+        assert_eq!(
+            Script::from_bytes_checked(p2wpkh.clone()).unwrap(),
+            Script::new(p2wpkh)
+        );
+        assert_matches!(
+            Script::from_bytes_checked(Vec::new()),
+            Err(BdkError::Generic { .. })
+        );
+        assert_matches!(
+            Script::from_bytes_checked(vec![0x51; 10_001]),
+            Err(BdkError::Generic { .. })
+        );
+        // a push of 20 bytes with only 2 bytes left
+        assert_matches!(
+            Script::from_bytes_checked(vec![0x00, 0x14, 0xc2, 0xe2]),
+            Err(BdkError::Generic { .. })
+        );
+    }
+
+    #[test]
+    fn test_to_qr_uri() {
+        let address =
+            Address::new("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt".to_string()).unwrap();
+        assert_eq!(
+            address.to_qr_uri(None, None, None),
+            "BITCOIN:TB1QL7W62ELX9UCW4PJ5LGW4L028HMUW80SNDTNTXT"
+        );
+        assert_eq!(
+            address.to_qr_uri(
+                Some(150_000_000),
+                Some("Alice & Bob".to_string()),
+                Some("Café #1".to_string())
+            ),
+            "BITCOIN:TB1QL7W62ELX9UCW4PJ5LGW4L028HMUW80SNDTNTXT?amount=1.5&label=Alice%20%26%20Bob&message=Caf%C3%A9%20%231"
+        );
+        assert_eq!(
+            address.to_qr_uri(Some(100_000), None, None),
+            "BITCOIN:TB1QL7W62ELX9UCW4PJ5LGW4L028HMUW80SNDTNTXT?amount=0.001"
+        );
+    }
 }
@@ -1,12 +1,21 @@
 use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::util::psbt::Input;
 use bdk::bitcoin::util::psbt::PartiallySignedTransaction as BdkPartiallySignedTransaction;
+use bdk::bitcoin::util::sighash::{Prevouts, SighashCache};
+use bdk::bitcoin::util::taproot::TapSighashHash;
+use bdk::bitcoin::{EcdsaSighashType, SchnorrSighashType, Sighash, TxOut};
 use bdk::bitcoincore_rpc::jsonrpc::serde_json;
+use bdk::keys::DescriptorSecretKey as BdkDescriptorSecretKey;
 use bdk::psbt::PsbtUtils;
+use bdk::signer::{InputSigner, SignerContext, SignerError, SignerWrapper};
+use bdk::SignOptions as BdkSignOptions;
 use std::ops::Deref;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 
-use crate::{BdkError, FeeRate, Transaction};
+use crate::{BdkError, DescriptorSecretKey, FeeRate, Transaction};
 
 #[derive(Debug)]
 pub(crate) struct PartiallySignedTransaction {
@@ -22,6 +31,24 @@ impl PartiallySignedTransaction {
         })
     }
 
+    /// Create a PSBT from an unsigned transaction, e.g. one built earlier and serialized, as the
+    /// starting point of an external signing flow. The PSBT has no input or output information yet.
+    /// Returns an error if any input has a script signature or witness.
+    pub(crate) fn from_unsigned_tx(tx: Arc<Transaction>) -> Result<Self, BdkError> {
+        let psbt = BdkPartiallySignedTransaction::from_unsigned_tx(tx.internal.clone())?;
+        Ok(PartiallySignedTransaction {
+            internal: Mutex::new(psbt),
+        })
+    }
+
+    /// A deep copy of the PSBT, e.g. for a coordinator to keep an unsigned copy for each signer
+    /// without serializing it to base64 and back. Changes to the copy don't affect this PSBT.
+    pub(crate) fn clone_psbt(&self) -> Arc<Self> {
+        Arc::new(PartiallySignedTransaction {
+            internal: Mutex::new(self.internal.lock().unwrap().clone()),
+        })
+    }
+
     pub(crate) fn serialize(&self) -> String {
         let psbt = self.internal.lock().unwrap().clone();
         psbt.to_string()
@@ -42,6 +69,7 @@ impl PartiallySignedTransaction {
     /// Combines this PartiallySignedTransaction with other PSBT as described by BIP 174.
     ///
     /// In accordance with BIP 174 this function is commutative i.e., `A.combine(B) == B.combine(A)`
+    /// Both PSBTs must have the same unsigned transaction, otherwise the error gives both txids.
     pub(crate) fn combine(
         &self,
         other: Arc<PartiallySignedTransaction>,
@@ -49,12 +77,65 @@ impl PartiallySignedTransaction {
         let other_psbt = other.internal.lock().unwrap().clone();
         let mut original_psbt = self.internal.lock().unwrap().clone();
 
+        let original_txid = original_psbt.unsigned_tx.txid();
+        let other_txid = other_psbt.unsigned_tx.txid();
+        if original_txid != other_txid {
+            return Err(BdkError::Generic {
+                error_message: format!(
+                    "Cannot combine PSBTs with different unsigned transactions: {} and {}",
+                    original_txid, other_txid
+                ),
+            });
+        }
         original_psbt.combine(other_psbt)?;
         Ok(Arc::new(PartiallySignedTransaction {
             internal: Mutex::new(original_psbt),
         }))
     }
 
+    /// Parse a base64 PSBT, e.g. one returned by a cosigner, and combine it with this one, like
+    /// combine.
+    pub(crate) fn combine_base64(
+        &self,
+        other: String,
+    ) -> Result<Arc<PartiallySignedTransaction>, BdkError> {
+        self.combine(Arc::new(PartiallySignedTransaction::new(other)?))
+    }
+
+    /// Sign the PSBT with a single secret key, independently of a wallet, e.g. to sign with only one
+    /// of the keys of a multisig. Extended keys only sign the inputs with a matching BIP32
+    /// derivation. Returns true if at least one signature was added, the PSBT is not finalized.
+    pub(crate) fn sign_with_key(
+        &self,
+        secret_key: Arc<DescriptorSecretKey>,
+    ) -> Result<bool, BdkError> {
+        let secp = Secp256k1::new();
+        let secret_key = secret_key
+            .descriptor_secret_key_mutex
+            .lock()
+            .unwrap()
+            .clone();
+        let sign_options = BdkSignOptions::default();
+        let mut psbt = self.internal.lock().unwrap();
+        let signatures_before = signature_count(&psbt);
+        for input_index in 0..psbt.inputs.len() {
+            let ctx = signer_context(&psbt, input_index);
+            match &secret_key {
+                BdkDescriptorSecretKey::XPrv(descriptor_x_key) => SignerWrapper::new(
+                    descriptor_x_key.clone(),
+                    ctx,
+                )
+                .sign_input(&mut psbt, input_index, &sign_options, &secp)?,
+                BdkDescriptorSecretKey::Single(single_priv) => SignerWrapper::new(
+                    single_priv.key,
+                    ctx,
+                )
+                .sign_input(&mut psbt, input_index, &sign_options, &secp)?,
+            }
+        }
+        Ok(signature_count(&psbt) > signatures_before)
+    }
+
     /// The total transaction fee amount, sum of input amounts minus sum of output amounts, in Sats.
     /// If the PSBT is missing a TxOut for an input returns None.
     pub(crate) fn fee_amount(&self) -> Option<u64> {
@@ -66,7 +147,58 @@ impl PartiallySignedTransaction {
     /// transaction.
     /// If the PSBT is missing a TxOut for an input returns None.
     pub(crate) fn fee_rate(&self) -> Option<Arc<FeeRate>> {
-        self.internal.lock().unwrap().fee_rate().map(Arc::new)
+        self.internal
+            .lock()
+            .unwrap()
+            .fee_rate()
+            .map(|fee_rate| Arc::new(fee_rate.into()))
+    }
+
+    /// The virtual size of the transaction in vbytes, to compute the exact fee rate from
+    /// fee_amount with your own rounding. Like fee_rate, this is only the final size once the PSBT
+    /// is finalized and all witness/signature data is added to the transaction.
+    pub(crate) fn vsize(&self) -> u64 {
+        self.internal.lock().unwrap().clone().extract_tx().vsize() as u64
+    }
+
+    /// The number of inputs of the unsigned transaction.
+    pub(crate) fn input_count(&self) -> u64 {
+        self.internal.lock().unwrap().unsigned_tx.input.len() as u64
+    }
+
+    /// The number of outputs of the unsigned transaction.
+    pub(crate) fn output_count(&self) -> u64 {
+        self.internal.lock().unwrap().unsigned_tx.output.len() as u64
+    }
+
+    /// The number of signatures present in each input, e.g. to show the signing progress of a
+    /// multisig transaction against the threshold of the wallet policy. Finalized inputs have no
+    /// partial signatures left, so they count as 0.
+    pub(crate) fn partial_sigs_count(&self) -> Vec<u64> {
+        self.internal
+            .lock()
+            .unwrap()
+            .inputs
+            .iter()
+            .map(|input| input_signature_count(input) as u64)
+            .collect()
+    }
+
+    /// The sighash an input commits to, e.g. to compare with the one shown by an external signer
+    /// before it signs. Taproot inputs give the sighash of a key path spend, which needs the
+    /// previous outputs of all the inputs unless the input is signed with SIGHASH_ANYONECANPAY.
+    /// The bytes are in the order they are signed in, not reversed like txids.
+    /// Returns an error if the previous outputs needed are missing from the PSBT.
+    pub(crate) fn sighash(&self, input_index: u32) -> Result<Vec<u8>, BdkError> {
+        let psbt = self.internal.lock().unwrap();
+        let input_index = input_index as usize;
+        if input_utxo(&psbt, input_index)?.script_pubkey.is_v1_p2tr() {
+            let sighash = taproot_key_spend_sighash(&psbt, input_index)?;
+            Ok(sighash.into_inner().to_vec())
+        } else {
+            let (sighash, _) = ecdsa_sighash(&psbt, input_index)?;
+            Ok(sighash.into_inner().to_vec())
+        }
     }
 
     /// Serialize the PSBT data structure as a String of JSON.
@@ -76,14 +208,155 @@ impl PartiallySignedTransaction {
     }
 }
 
+/// The number of signatures in all the PSBT inputs.
+fn signature_count(psbt: &BdkPartiallySignedTransaction) -> usize {
+    psbt.inputs.iter().map(input_signature_count).sum()
+}
+
+/// The number of signatures in a PSBT input, ECDSA or Schnorr.
+fn input_signature_count(input: &Input) -> usize {
+    input.partial_sigs.len() + input.tap_script_sigs.len() + input.tap_key_sig.iter().count()
+}
+
+/// The context to sign a PSBT input in, from the type of the output it spends.
+fn signer_context(psbt: &BdkPartiallySignedTransaction, input_index: usize) -> SignerContext {
+    let input = &psbt.inputs[input_index];
+    let vout = psbt.unsigned_tx.input[input_index].previous_output.vout as usize;
+    let script_pubkey = match (&input.witness_utxo, &input.non_witness_utxo) {
+        (Some(witness_utxo), _) => Some(witness_utxo.script_pubkey.clone()),
+        (None, Some(non_witness_utxo)) => non_witness_utxo
+            .output
+            .get(vout)
+            .map(|tx_out| tx_out.script_pubkey.clone()),
+        (None, None) => None,
+    };
+    let script = input.redeem_script.clone().or(script_pubkey);
+    match script {
+        // the signer checks the key is the input's internal key before a key path spend
+        _ if input.tap_internal_key.is_some() => SignerContext::Tap {
+            is_internal_key: true,
+        },
+        Some(script) if script.is_v1_p2tr() => SignerContext::Tap {
+            is_internal_key: true,
+        },
+        Some(script) if script.is_v0_p2wpkh() || script.is_v0_p2wsh() => SignerContext::Segwitv0,
+        _ => SignerContext::Legacy,
+    }
+}
+
+/// The previous output spent by a PSBT input, from its witness or non-witness UTXO.
+fn input_utxo(
+    psbt: &BdkPartiallySignedTransaction,
+    input_index: usize,
+) -> Result<TxOut, SignerError> {
+    let (input, tx_input) = match (
+        psbt.inputs.get(input_index),
+        psbt.unsigned_tx.input.get(input_index),
+    ) {
+        (Some(input), Some(tx_input)) => (input, tx_input),
+        _ => return Err(SignerError::InputIndexOutOfRange),
+    };
+    match (&input.witness_utxo, &input.non_witness_utxo) {
+        (Some(witness_utxo), _) => Ok(witness_utxo.clone()),
+        (None, Some(non_witness_utxo)) => non_witness_utxo
+            .output
+            .get(tx_input.previous_output.vout as usize)
+            .cloned()
+            .ok_or(SignerError::InvalidNonWitnessUtxo),
+        (None, None) => Err(SignerError::MissingNonWitnessUtxo),
+    }
+}
+
+/// Compute the ECDSA sighash of a legacy or segwit v0 PSBT input, and the sighash type it commits
+/// to (SIGHASH_ALL unless the input specifies one).
+pub(crate) fn ecdsa_sighash(
+    psbt: &BdkPartiallySignedTransaction,
+    input_index: usize,
+) -> Result<(Sighash, EcdsaSighashType), SignerError> {
+    let utxo = input_utxo(psbt, input_index)?;
+    let input = &psbt.inputs[input_index];
+    let sighash_type = match input.sighash_type {
+        Some(sighash_type) => sighash_type
+            .ecdsa_hash_ty()
+            .map_err(|_| SignerError::NonStandardSighash)?,
+        None => EcdsaSighashType::All,
+    };
+
+    // for P2SH inputs the redeem script is either the script to sign or the nested segwit program
+    let script = input.redeem_script.as_ref().unwrap_or(&utxo.script_pubkey);
+    let mut sighash_cache = SighashCache::new(&psbt.unsigned_tx);
+    let sighash = if script.is_v0_p2wpkh() {
+        let script_code = script
+            .p2wpkh_script_code()
+            .ok_or(SignerError::MissingWitnessScript)?;
+        sighash_cache.segwit_signature_hash(input_index, &script_code, utxo.value, sighash_type)
+    } else if script.is_v0_p2wsh() {
+        let witness_script = input
+            .witness_script
+            .as_ref()
+            .ok_or(SignerError::MissingWitnessScript)?;
+        sighash_cache.segwit_signature_hash(input_index, witness_script, utxo.value, sighash_type)
+    } else if script.is_witness_program() {
+        // taproot inputs are signed with schnorr signatures
+        return Err(SignerError::InvalidSighash);
+    } else {
+        sighash_cache.legacy_signature_hash(input_index, script, sighash_type.to_u32())
+    }
+    .map_err(SignerError::SighashError)?;
+    Ok((sighash, sighash_type))
+}
+
+/// Compute the Schnorr sighash of a taproot PSBT input spent with the key path. It commits to the
+/// previous outputs of all the inputs, or only the spent one with SIGHASH_ANYONECANPAY.
+fn taproot_key_spend_sighash(
+    psbt: &BdkPartiallySignedTransaction,
+    input_index: usize,
+) -> Result<TapSighashHash, SignerError> {
+    let utxo = input_utxo(psbt, input_index)?;
+    let sighash_type = psbt.inputs[input_index]
+        .schnorr_hash_ty()
+        .map_err(|_| SignerError::NonStandardSighash)?;
+    let mut sighash_cache = SighashCache::new(&psbt.unsigned_tx);
+    let sighash = match sighash_type {
+        SchnorrSighashType::AllPlusAnyoneCanPay
+        | SchnorrSighashType::NonePlusAnyoneCanPay
+        | SchnorrSighashType::SinglePlusAnyoneCanPay => sighash_cache
+            .taproot_key_spend_signature_hash(
+                input_index,
+                &Prevouts::One(input_index, utxo),
+                sighash_type,
+            ),
+        _ => {
+            let utxos = (0..psbt.inputs.len())
+                .map(|index| input_utxo(psbt, index))
+                .collect::<Result<Vec<_>, _>>()?;
+            sighash_cache.taproot_key_spend_signature_hash(
+                input_index,
+                &Prevouts::All(&utxos),
+                sighash_type,
+            )
+        }
+    };
+    sighash.map_err(SignerError::SighashError)
+}
+
 // The goal of these tests to to ensure `bdk-ffi` intermediate code correctly calls `bdk` APIs.
 // These tests should not be used to verify `bdk` behavior that is already tested in the `bdk`
 // crate.
 #[cfg(test)]
 mod test {
+    use crate::keys::DescriptorSecretKey;
+    use crate::psbt::PartiallySignedTransaction;
+    use crate::wallet::test::{funded_test_wallet, test_recipient};
     use crate::wallet::{TxBuilder, Wallet};
+    use crate::BdkError;
+    use assert_matches::assert_matches;
+    use bdk::bitcoin::secp256k1::{Message, Secp256k1};
+    use bdk::bitcoin::util::psbt::raw::ProprietaryKey;
+    use bdk::bitcoin::{Witness, XOnlyPublicKey};
+    use bdk::signer::SignerError;
     use bdk::wallet::get_funded_wallet;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     #[test]
     fn test_psbt_fee() {
@@ -115,5 +388,228 @@ mod test {
 
         assert!(tx_builder_result.psbt.fee_amount().is_some());
         assert_eq!(tx_builder_result.psbt.fee_amount().unwrap(), 220);
+        // 220 sat / 82 vB
+        assert_eq!(tx_builder_result.psbt.vsize(), 82);
+
+        assert_eq!(tx_builder_result.psbt.input_count(), 1);
+        assert_eq!(tx_builder_result.psbt.output_count(), 1);
+    }
+
+    #[test]
+    fn test_sign_with_key() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let psbt = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+
+        // a key which isn't part of the wallet doesn't sign
+        let other_key = DescriptorSecretKey::from_string("tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/*".to_string()).unwrap();
+        assert!(!psbt.sign_with_key(Arc::new(other_key)).unwrap());
+        assert_eq!(psbt.partial_sigs_count(), vec![0]);
+
+        let wallet_key = DescriptorSecretKey::from_string("tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/*".to_string()).unwrap();
+        assert!(psbt.sign_with_key(Arc::new(wallet_key)).unwrap());
+        assert_eq!(
+            psbt.internal.lock().unwrap().inputs[0].partial_sigs.len(),
+            1
+        );
+        assert_eq!(psbt.partial_sigs_count(), vec![1]);
+    }
+
+    #[test]
+    fn test_from_unsigned_tx() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let tx = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt
+            .extract_tx();
+
+        let psbt = PartiallySignedTransaction::from_unsigned_tx(tx.clone()).unwrap();
+        assert_eq!(psbt.txid(), tx.txid());
+        assert_eq!(psbt.input_count(), 1);
+        assert!(psbt.internal.lock().unwrap().inputs[0]
+            .witness_utxo
+            .is_none());
+
+        // a signed transaction isn't accepted
+        let mut signed_tx = tx.internal.clone();
+        signed_tx.input[0].witness = Witness::from_vec(vec![vec![1; 72], vec![2; 33]]);
+        assert!(PartiallySignedTransaction::from_unsigned_tx(Arc::new(signed_tx.into())).is_err());
+    }
+
+    #[test]
+    fn test_clone_psbt() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let psbt = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+
+        let copy = psbt.clone_psbt();
+        assert_eq!(copy.serialize(), psbt.serialize());
+        let key = DescriptorSecretKey::from_string("tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/*".to_string()).unwrap();
+        assert!(copy.sign_with_key(Arc::new(key)).unwrap());
+        assert_eq!(copy.partial_sigs_count(), vec![1]);
+        assert_eq!(psbt.partial_sigs_count(), vec![0]);
+    }
+
+    #[test]
+    fn test_sighash() {
+        let secp = Secp256k1::verification_only();
+        let drain_to_script = test_recipient();
+        let key = "cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW";
+        let drain_psbt = |descriptor: &str| {
+            let (test_wallet, _) = funded_test_wallet(descriptor);
+            let psbt = TxBuilder::new()
+                .drain_wallet()
+                .drain_to(drain_to_script.clone())
+                .finish(&test_wallet)
+                .unwrap()
+                .psbt;
+            let secret_key = DescriptorSecretKey::from_string(key.to_string()).unwrap();
+            assert!(psbt.sign_with_key(Arc::new(secret_key)).unwrap());
+            psbt
+        };
+
+        // the signature of the segwit v0 input is valid for the sighash
+        let psbt = drain_psbt(&format!("wpkh({})", key));
+        let sighash = psbt.sighash(0).unwrap();
+        assert_eq!(sighash.len(), 32);
+        let message = Message::from_slice(&sighash).unwrap();
+        let input = psbt.internal.lock().unwrap().inputs[0].clone();
+        let (public_key, signature) = input.partial_sigs.iter().next().unwrap();
+        assert!(secp
+            .verify_ecdsa(&message, &signature.sig, &public_key.inner)
+            .is_ok());
+
+        // the taproot input gives the sighash of the key path spend
+        let psbt = drain_psbt(&format!("tr({})", key));
+        let sighash = psbt.sighash(0).unwrap();
+        let message = Message::from_slice(&sighash).unwrap();
+        let input = psbt.internal.lock().unwrap().inputs[0].clone();
+        let output_key =
+            XOnlyPublicKey::from_slice(&input.witness_utxo.unwrap().script_pubkey[2..]).unwrap();
+        assert!(secp
+            .verify_schnorr(&input.tap_key_sig.unwrap().sig, &message, &output_key)
+            .is_ok());
+
+        assert_eq!(
+            psbt.sighash(1).unwrap_err(),
+            BdkError::from(SignerError::InputIndexOutOfRange)
+        );
+        psbt.internal.lock().unwrap().inputs[0].witness_utxo = None;
+        assert_eq!(
+            psbt.sighash(0).unwrap_err(),
+            BdkError::from(SignerError::MissingNonWitnessUtxo)
+        );
+    }
+
+    #[test]
+    fn test_combine_base64() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let psbt = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+
+        // the cosigner signs their own copy and returns it as base64
+        let cosigner_psbt = PartiallySignedTransaction::new(psbt.serialize()).unwrap();
+        let cosigner_key = DescriptorSecretKey::from_string("tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/*".to_string()).unwrap();
+        assert!(cosigner_psbt.sign_with_key(Arc::new(cosigner_key)).unwrap());
+
+        let combined = psbt.combine_base64(cosigner_psbt.serialize()).unwrap();
+        assert_eq!(
+            combined.internal.lock().unwrap().inputs[0]
+                .partial_sigs
+                .len(),
+            1
+        );
+        assert_eq!(combined.serialize(), cosigner_psbt.serialize());
+
+        assert!(psbt.combine_base64("not a psbt".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_combine_different_transactions() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let tx_builder = TxBuilder::new().drain_wallet().drain_to(drain_to_script);
+        let psbt = tx_builder.fee_rate(1.0).finish(&test_wallet).unwrap().psbt;
+        let other_psbt = tx_builder.fee_rate(5.0).finish(&test_wallet).unwrap().psbt;
+
+        let error = psbt.combine(other_psbt.clone()).unwrap_err();
+        assert_matches!(error, BdkError::Generic { error_message: message } => {
+            assert!(message.contains(&psbt.txid()));
+            assert!(message.contains(&other_psbt.txid()));
+        });
+    }
+
+    #[test]
+    fn test_proprietary_fields_preserved() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let psbt = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+        // a copy without the proprietary fields, e.g. held by a cosigner
+        let plain = psbt.serialize();
+
+        let key = |subtype: u8| ProprietaryKey {
+            prefix: b"protocol".to_vec(),
+            subtype,
+            key: vec![subtype],
+        };
+        {
+            let mut internal = psbt.internal.lock().unwrap();
+            internal.proprietary.insert(key(0), b"global".to_vec());
+            internal.inputs[0]
+                .proprietary
+                .insert(key(1), b"input".to_vec());
+            internal.outputs[0]
+                .proprietary
+                .insert(key(2), b"output".to_vec());
+        }
+        let assert_preserved = |psbt: &PartiallySignedTransaction| {
+            let internal = psbt.internal.lock().unwrap();
+            assert_eq!(internal.proprietary[&key(0)], b"global".to_vec());
+            assert_eq!(internal.inputs[0].proprietary[&key(1)], b"input".to_vec());
+            assert_eq!(internal.outputs[0].proprietary[&key(2)], b"output".to_vec());
+        };
+
+        let deserialized = PartiallySignedTransaction::new(psbt.serialize()).unwrap();
+        assert_preserved(&deserialized);
+        assert_eq!(deserialized.serialize(), psbt.serialize());
+
+        let other = PartiallySignedTransaction::new(plain.clone()).unwrap();
+        assert_preserved(&psbt.combine(Arc::new(other)).unwrap());
+        let other = PartiallySignedTransaction::new(plain).unwrap();
+        assert_preserved(&other.combine_base64(psbt.serialize()).unwrap());
+
+        assert!(test_wallet.sign(&deserialized, None).unwrap());
+        assert_preserved(&deserialized);
+        assert_preserved(&PartiallySignedTransaction::new(deserialized.serialize()).unwrap());
     }
 }
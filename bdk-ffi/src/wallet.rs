@@ -1,24 +1,39 @@
 use bdk::bitcoin::blockdata::script::Script as BdkScript;
-use bdk::bitcoin::{Address as BdkAddress, Network, OutPoint as BdkOutPoint, Sequence, Txid};
+use bdk::bitcoin::util::bip32::{ChildNumber, Fingerprint};
+use bdk::bitcoin::util::psbt::PartiallySignedTransaction as BdkPartiallySignedTransaction;
+use bdk::bitcoin::{
+    Address as BdkAddress, Amount, LockTime, Network, OutPoint as BdkOutPoint, Sequence,
+    Transaction as BdkTransaction, Txid,
+};
+use bdk::bitcoincore_rpc::jsonrpc::serde_json;
+use bdk::blockchain::any::AnyBlockchain;
+use bdk::blockchain::{GetHeight, Progress as BdkProgress, WalletSync};
 use bdk::database::any::AnyDatabase;
-use bdk::database::{AnyDatabaseConfig, ConfigurableDatabase};
+use bdk::database::{AnyDatabaseConfig, BatchDatabase, ConfigurableDatabase, Database};
+use bdk::miniscript::ForEachKey;
+use bdk::signer::{SignerCommon, SignerId, SignerOrdering};
 use bdk::wallet::tx_builder::ChangeSpendPolicy;
 use bdk::{
-    FeeRate, LocalUtxo as BdkLocalUtxo, SignOptions as BdkSignOptions,
-    SyncOptions as BdkSyncOptions, Wallet as BdkWallet,
+    BlockTime, FeeRate, KeychainKind, LocalUtxo as BdkLocalUtxo, SignOptions as BdkSignOptions,
+    SyncOptions as BdkSyncOptions, TransactionDetails as BdkTransactionDetails,
+    Wallet as BdkWallet,
 };
-use std::collections::HashSet;
-use std::ops::Deref;
+use log::{error, info, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::ops::{Deref, DerefMut};
 use std::str::FromStr;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
 
 use crate::blockchain::Blockchain;
 use crate::database::DatabaseConfig;
 use crate::descriptor::Descriptor;
 use crate::psbt::PartiallySignedTransaction;
 use crate::{
-    AddressIndex, AddressInfo, Balance, BdkError, LocalUtxo, OutPoint, Progress, ProgressHolder,
-    RbfValue, Script, ScriptAmount, TransactionDetails, TxBuilderResult,
+    fee_rate, Address, AddressIndex, AddressInfo, Balance, BdkError, LocalUtxo, OutPoint, Progress,
+    ProgressHolder, RbfValue, Script, ScriptAmount, ScriptType, Signer, SignerHolder, Transaction,
+    TransactionDetails, TxBuilderResult,
 };
 
 #[derive(Debug)]
@@ -38,6 +53,16 @@ impl Wallet {
         network: Network,
         database_config: DatabaseConfig,
     ) -> Result<Self, BdkError> {
+        if let Some(change_descriptor) = &change_descriptor {
+            // descriptors the public keys can't derive, e.g. with a hardened wildcard, can't be
+            // compared and are accepted
+            if let Ok(false) = descriptor.is_keychain_distinct(change_descriptor) {
+                return Err(BdkError::Generic {
+                    error_message: "The external and change descriptors derive the same scripts"
+                        .to_string(),
+                });
+            }
+        }
         let any_database_config = match database_config {
             DatabaseConfig::Memory => AnyDatabaseConfig::Memory(()),
             DatabaseConfig::Sled { config } => AnyDatabaseConfig::Sled(config),
@@ -56,30 +81,108 @@ impl Wallet {
         Ok(Wallet { wallet_mutex })
     }
 
+    /// Like Wallet.new without a change descriptor, but receive change in scripts of the given
+    /// type, see Descriptor.change_descriptor for how the change descriptor is derived.
+    pub(crate) fn new_with_change_script_type(
+        descriptor: Arc<Descriptor>,
+        change_script_type: ScriptType,
+        network: Network,
+        database_config: DatabaseConfig,
+    ) -> Result<Self, BdkError> {
+        let change_descriptor = descriptor.change_descriptor(change_script_type, network)?;
+        Self::new(
+            descriptor,
+            Some(change_descriptor),
+            network,
+            database_config,
+        )
+    }
+
+    /// Create a wallet backed by an in-memory database, which is lost when the wallet is dropped.
+    /// This is meant for stateless uses that don't sync, like signing PSBTs on an air-gapped
+    /// device. It is the same as Wallet.new with DatabaseConfig.Memory.
+    pub(crate) fn new_no_persist(
+        descriptor: Arc<Descriptor>,
+        change_descriptor: Option<Arc<Descriptor>>,
+        network: Network,
+    ) -> Result<Self, BdkError> {
+        Self::new(
+            descriptor,
+            change_descriptor,
+            network,
+            DatabaseConfig::Memory,
+        )
+    }
+
     pub(crate) fn get_wallet(&self) -> MutexGuard<BdkWallet<AnyDatabase>> {
         self.wallet_mutex.lock().expect("wallet")
     }
 
+    /// Like get_wallet, but returns None instead of blocking if the wallet is in use.
+    fn try_get_wallet(&self) -> Option<MutexGuard<BdkWallet<AnyDatabase>>> {
+        match self.wallet_mutex.try_lock() {
+            Ok(wallet) => Some(wallet),
+            Err(TryLockError::WouldBlock) => None,
+            Err(TryLockError::Poisoned(_)) => panic!("wallet"),
+        }
+    }
+
     /// Get the Bitcoin network the wallet is using.
     pub(crate) fn network(&self) -> Network {
         self.get_wallet().network()
     }
 
-    /// Sync the internal database with the blockchain.
+    /// Sync the internal database with the blockchain. Fails with BdkError.InvalidNetwork if the
+    /// blockchain backend is on another network than the wallet, see Blockchain.check_network.
     pub(crate) fn sync(
         &self,
         blockchain: &Blockchain,
         progress: Option<Box<dyn Progress>>,
     ) -> Result<(), BdkError> {
-        let bdk_sync_opts = BdkSyncOptions {
-            progress: progress.map(|p| {
-                Box::new(ProgressHolder { progress: p })
-                    as Box<(dyn bdk::blockchain::Progress + 'static)>
-            }),
+        let any_blockchain = blockchain.get_blockchain();
+        sync_wallet(&self.get_wallet(), blockchain, &any_blockchain, progress)
+    }
+
+    /// Sync like sync, but return a BdkError.Generic error instead of waiting if the wallet or
+    /// the blockchain is in use, e.g. by a sync in progress on another thread. This keeps a manual
+    /// refresh from the UI thread from freezing while a background sync runs.
+    pub(crate) fn try_sync(
+        &self,
+        blockchain: &Blockchain,
+        progress: Option<Box<dyn Progress>>,
+    ) -> Result<(), BdkError> {
+        let sync_in_progress = || BdkError::Generic {
+            error_message: "sync already in progress".to_string(),
         };
+        // locked in the same order as sync
+        let any_blockchain = blockchain
+            .try_get_blockchain()
+            .ok_or_else(sync_in_progress)?;
+        let wallet = self.try_get_wallet().ok_or_else(sync_in_progress)?;
+        sync_wallet(&wallet, blockchain, &any_blockchain, progress)
+    }
+
+    /// Sync the wallet like sync and report what changed, e.g. to decide whether to refresh the UI
+    /// or notify the user after a background sync.
+    pub(crate) fn sync_with_report(
+        &self,
+        blockchain: &Blockchain,
+        progress: Option<Box<dyn Progress>>,
+    ) -> Result<SyncReport, BdkError> {
+        let before = self.confirmation_heights()?;
+        self.sync(blockchain, progress)?;
+        let after = self.confirmation_heights()?;
+        Ok(SyncReport::new(&before, &after))
+    }
 
-        let blockchain = blockchain.get_blockchain();
-        self.get_wallet().sync(blockchain.deref(), bdk_sync_opts)
+    /// The confirmation height of each wallet transaction, None for unconfirmed transactions.
+    fn confirmation_heights(&self) -> Result<HashMap<Txid, Option<u32>>, BdkError> {
+        Ok(self
+            .get_wallet()
+            .list_transactions(false)?
+            .into_iter()
+            .map(|tx| (tx.txid, tx.confirmation_time.map(|time| time.height)))
+            .collect())
     }
 
     /// Return a derived address using the external descriptor, see AddressIndex for available address index selection
@@ -89,6 +192,7 @@ impl Wallet {
         self.get_wallet()
             .get_address(address_index.into())
             .map(AddressInfo::from)
+            .map_err(BdkError::from)
     }
 
     /// Return a derived address using the internal (change) descriptor.
@@ -105,12 +209,82 @@ impl Wallet {
         self.get_wallet()
             .get_internal_address(address_index.into())
             .map(AddressInfo::from)
+            .map_err(BdkError::from)
+    }
+
+    /// The script_pubkey at `index` of a keychain, without revealing it, e.g. to subscribe to its
+    /// script hash on an Electrum server. Like get_internal_address, the internal keychain uses the
+    /// external descriptor if the wallet doesn't have a change descriptor. Unlike AddressIndex.Peek
+    /// this also works for descriptors without an address format, e.g. bare multisig.
+    pub(crate) fn peek_script(
+        &self,
+        keychain: KeychainKind,
+        index: u32,
+    ) -> Result<Arc<Script>, BdkError> {
+        ChildNumber::from_normal_idx(index).map_err(|e| BdkError::Generic {
+            error_message: e.to_string(),
+        })?;
+        let wallet = self.get_wallet();
+        let descriptor = wallet
+            .get_descriptor_for_keychain(keychain)
+            .derived_descriptor(wallet.secp_ctx(), index)
+            .map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        Ok(Arc::new(descriptor.script_pubkey().into()))
     }
 
     /// Return the balance, meaning the sum of this wallet’s unspent outputs’ values. Note that this method only operates
     /// on the internal database, which first needs to be Wallet.sync manually.
     pub(crate) fn get_balance(&self) -> Result<Balance, BdkError> {
-        self.get_wallet().get_balance().map(|b| b.into())
+        Ok(self.get_wallet().get_balance()?.into())
+    }
+
+    /// Return the balance of the wallet as of the block at `height`, counting only the transactions
+    /// confirmed at or before it, e.g. for an end of year report. Unconfirmed transactions are
+    /// ignored, so the pending balances are always 0. Note that this method only operates on the
+    /// internal database, which first needs to be Wallet.sync manually.
+    pub(crate) fn get_balance_at_height(&self, height: u32) -> Result<Balance, BdkError> {
+        let wallet = self.get_wallet();
+        let confirmed_txs: Vec<(BdkTransaction, u32)> = wallet
+            .list_transactions(true)?
+            .into_iter()
+            .filter_map(
+                |details| match (details.transaction, details.confirmation_time) {
+                    (Some(tx), Some(time)) if time.height <= height => Some((tx, time.height)),
+                    _ => None,
+                },
+            )
+            .collect();
+        let spent: HashSet<BdkOutPoint> = confirmed_txs
+            .iter()
+            .flat_map(|(tx, _)| tx.input.iter().map(|input| input.previous_output))
+            .collect();
+
+        let mut immature = 0;
+        let mut confirmed = 0;
+        for (tx, confirmation_height) in &confirmed_txs {
+            let txid = tx.txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                let outpoint = BdkOutPoint::new(txid, vout as u32);
+                if spent.contains(&outpoint) || !wallet.is_mine(&output.script_pubkey)? {
+                    continue;
+                }
+                if tx.is_coin_base() && height < confirmation_height + COINBASE_MATURITY {
+                    immature += output.value;
+                } else {
+                    confirmed += output.value;
+                }
+            }
+        }
+        Ok(Balance {
+            immature,
+            trusted_pending: 0,
+            untrusted_pending: 0,
+            confirmed,
+            spendable: confirmed,
+            total: immature + confirmed,
+        })
     }
 
     /// Sign a transaction with all the wallet's signers, in the order specified by every signer's
@@ -127,10 +301,141 @@ impl Wallet {
         sign_options: Option<SignOptions>,
     ) -> Result<bool, BdkError> {
         let mut psbt = psbt.internal.lock().unwrap();
-        self.get_wallet().sign(
+        Ok(self.get_wallet().sign(
             &mut psbt,
             sign_options.map(SignOptions::into).unwrap_or_default(),
-        )
+        )?)
+    }
+
+    /// Sign a transaction like Wallet.sign, and return the finalized transaction ready to be
+    /// broadcast, or None if the PSBT couldn't be finalized, e.g. because it still needs the
+    /// signatures of other cosigners.
+    pub(crate) fn sign_and_extract(
+        &self,
+        psbt: &PartiallySignedTransaction,
+        sign_options: Option<SignOptions>,
+    ) -> Result<Option<Arc<Transaction>>, BdkError> {
+        if self.sign(psbt, sign_options)? {
+            Ok(Some(psbt.extract_tx()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Build a transaction paying the recipients at the given fee rate (sat/vB), sign it and
+    /// broadcast it, and return its txid. Nothing is broadcast if the transaction can't be fully
+    /// signed and finalized, e.g. for watch-only or multisig wallets, which need the separate
+    /// TxBuilder, Wallet.sign and Blockchain.broadcast steps instead. Once broadcast, the
+    /// transaction is saved in the wallet as unconfirmed, so its inputs aren't spent again before
+    /// the next sync.
+    pub(crate) fn send(
+        &self,
+        blockchain: &Blockchain,
+        recipients: Vec<ScriptAmount>,
+        fee_rate: f32,
+        enable_rbf: bool,
+    ) -> Result<String, BdkError> {
+        self.send_with(recipients, fee_rate, enable_rbf, |tx| {
+            blockchain.broadcast(tx)
+        })
+    }
+
+    fn send_with<B: FnOnce(&Transaction) -> Result<(), BdkError>>(
+        &self,
+        recipients: Vec<ScriptAmount>,
+        fee_rate: f32,
+        enable_rbf: bool,
+        broadcast: B,
+    ) -> Result<String, BdkError> {
+        let mut tx_builder = TxBuilder::new()
+            .set_recipients(recipients)
+            .fee_rate(fee_rate);
+        if enable_rbf {
+            tx_builder = tx_builder.enable_rbf();
+        }
+        let psbt = tx_builder.finish(self)?.psbt;
+        let tx = self
+            .sign_and_extract(&psbt, None)?
+            .ok_or_else(|| BdkError::Generic {
+                error_message: "The transaction couldn't be finalized, it wasn't broadcast"
+                    .to_string(),
+            })?;
+        broadcast(&tx)?;
+        // the transaction was broadcast, so a failed insert isn't an error, the next sync adds it
+        if let Err(e) = self.insert_tx(tx.clone(), None) {
+            warn!("Saving the sent transaction {} failed: {}", tx.txid(), e);
+        }
+        Ok(tx.txid())
+    }
+
+    /// Add an external signer, e.g. a hardware wallet or a remote HSM, holding the keys derived from
+    /// the master key with the given fingerprint. During Wallet.sign the signer is asked to sign
+    /// the legacy and segwit v0 inputs whose BIP32 derivations use one of its keys that hasn't
+    /// signed yet.
+    pub(crate) fn add_signer(
+        &self,
+        keychain: KeychainKind,
+        fingerprint: String,
+        signer: Box<dyn Signer>,
+    ) -> Result<(), BdkError> {
+        let fingerprint =
+            Fingerprint::from_str(fingerprint.as_str()).map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+        self.get_wallet().add_signer(
+            keychain,
+            SignerOrdering::default(),
+            Arc::new(SignerHolder {
+                fingerprint,
+                signer,
+            }),
+        );
+        Ok(())
+    }
+
+    /// List the keys of the wallet descriptors, once per master key fingerprint, and whether the
+    /// wallet holds a signer for each of them. This can drive backup reminders like "you have the
+    /// private key for 1 of 3 cosigners".
+    pub(crate) fn get_signers(&self) -> Result<Vec<SignerInfo>, BdkError> {
+        let wallet = self.get_wallet();
+        let secp = wallet.secp_ctx();
+        let mut fingerprints = Vec::new();
+        let mut secret_fingerprints = HashSet::new();
+        for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+            if let Some(descriptor) = wallet.public_descriptor(*keychain)? {
+                descriptor.for_each_key(|key| {
+                    let fingerprint = key.master_fingerprint();
+                    if !fingerprints.contains(&fingerprint) {
+                        fingerprints.push(fingerprint);
+                    }
+                    true
+                });
+            }
+            for signer in wallet.get_signers(*keychain).signers() {
+                match signer.descriptor_secret_key() {
+                    Some(secret_key) => {
+                        let public_key =
+                            secret_key.to_public(secp).map_err(|e| BdkError::Generic {
+                                error_message: e.to_string(),
+                            })?;
+                        secret_fingerprints.insert(public_key.master_fingerprint());
+                    }
+                    // signers added with Wallet.add_signer only have an id
+                    None => {
+                        if let SignerId::Fingerprint(fingerprint) = signer.id(secp) {
+                            secret_fingerprints.insert(fingerprint);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(fingerprints
+            .into_iter()
+            .map(|fingerprint| SignerInfo {
+                fingerprint: fingerprint.to_string(),
+                has_secret: secret_fingerprints.contains(&fingerprint),
+            })
+            .collect())
     }
 
     /// Return the list of transactions made and received by the wallet. Note that this method only operate on the internal database, which first needs to be [Wallet.sync] manually.
@@ -138,499 +443,3095 @@ impl Wallet {
         &self,
         include_raw: bool,
     ) -> Result<Vec<TransactionDetails>, BdkError> {
-        let transaction_details = self.get_wallet().list_transactions(include_raw)?;
+        self.list_transactions_filtered(TransactionFilter::All, include_raw)
+    }
+
+    /// Like list_transactions, but only return the transactions matching the filter, e.g. for
+    /// separate pending and confirmed views of a large history.
+    pub(crate) fn list_transactions_filtered(
+        &self,
+        filter: TransactionFilter,
+        include_raw: bool,
+    ) -> Result<Vec<TransactionDetails>, BdkError> {
+        // the raw transactions are needed to find the conflicts
+        let transaction_details = self.get_wallet().list_transactions(true)?;
+        // coinbase inputs all spend the null outpoint, but don't conflict with each other
+        let spent_outpoints = |tx: &BdkTransaction| {
+            tx.input
+                .iter()
+                .map(|input| input.previous_output)
+                .filter(|outpoint| !outpoint.is_null())
+                .collect::<Vec<_>>()
+        };
+        let mut spenders: HashMap<BdkOutPoint, Vec<Txid>> = HashMap::new();
+        for details in &transaction_details {
+            if let Some(tx) = &details.transaction {
+                for outpoint in spent_outpoints(tx) {
+                    spenders.entry(outpoint).or_default().push(details.txid);
+                }
+            }
+        }
         Ok(transaction_details
             .into_iter()
-            .map(TransactionDetails::from)
+            .filter(|details| match filter {
+                TransactionFilter::All => true,
+                TransactionFilter::Confirmed => details.confirmation_time.is_some(),
+                TransactionFilter::Unconfirmed => details.confirmation_time.is_none(),
+            })
+            .map(|mut details| {
+                let mut conflicts: Vec<String> = Vec::new();
+                for outpoint in details.transaction.iter().flat_map(spent_outpoints) {
+                    for txid in &spenders[&outpoint] {
+                        let txid = txid.to_string();
+                        if txid != details.txid.to_string() && !conflicts.contains(&txid) {
+                            conflicts.push(txid);
+                        }
+                    }
+                }
+                // the fee rate needs the raw transaction too
+                let fee_rate = fee_rate(&details);
+                if !include_raw {
+                    details.transaction = None;
+                }
+                TransactionDetails {
+                    conflicts,
+                    fee_rate,
+                    ..TransactionDetails::from(details)
+                }
+            })
             .collect())
     }
 
     /// Return the list of unspent outputs of this wallet. Note that this method only operates on the internal database,
     /// which first needs to be Wallet.sync manually.
     pub(crate) fn list_unspent(&self) -> Result<Vec<LocalUtxo>, BdkError> {
-        let unspents: Vec<BdkLocalUtxo> = self.get_wallet().list_unspent()?;
-        Ok(unspents.into_iter().map(LocalUtxo::from).collect())
+        let wallet = self.get_wallet();
+        let unspents: Vec<BdkLocalUtxo> = wallet.list_unspent()?;
+        unspents
+            .into_iter()
+            .map(|utxo| local_utxo(&wallet, utxo))
+            .collect()
     }
-}
-
-/// Options for a software signer
-///
-/// Adjust the behavior of our software signers and the way a transaction is finalized
-#[derive(Debug, Clone, Default)]
-pub struct SignOptions {
-    /// Whether the signer should trust the `witness_utxo`, if the `non_witness_utxo` hasn't been
-    /// provided
-    ///
-    /// Defaults to `false` to mitigate the "SegWit bug" which should trick the wallet into
-    /// paying a fee larger than expected.
-    ///
-    /// Some wallets, especially if relatively old, might not provide the `non_witness_utxo` for
-    /// SegWit transactions in the PSBT they generate: in those cases setting this to `true`
-    /// should correctly produce a signature, at the expense of an increased trust in the creator
-    /// of the PSBT.
-    ///
-    /// For more details see: <https://blog.trezor.io/details-of-firmware-updates-for-trezor-one-version-1-9-1-and-trezor-model-t-version-2-3-1-1eba8f60f2dd>
-    pub trust_witness_utxo: bool,
-
-    /// Whether the wallet should assume a specific height has been reached when trying to finalize
-    /// a transaction
-    ///
-    /// The wallet will only "use" a timelock to satisfy the spending policy of an input if the
-    /// timelock height has already been reached. This option allows overriding the "current height" to let the
-    /// wallet use timelocks in the future to spend a coin.
-    pub assume_height: Option<u32>,
 
-    /// Whether the signer should use the `sighash_type` set in the PSBT when signing, no matter
-    /// what its value is
-    ///
-    /// Defaults to `false` which will only allow signing using `SIGHASH_ALL`.
-    pub allow_all_sighashes: bool,
-
-    /// Whether to remove partial signatures from the PSBT inputs while finalizing PSBT.
-    ///
-    /// Defaults to `true` which will remove partial signatures during finalization.
-    pub remove_partial_sigs: bool,
+    /// Return the wallet's unspent coinbase outputs which can't be spent yet at `current_height`,
+    /// with the height at which each matures (its confirmation height + 100), e.g. to show a
+    /// countdown. Without a height, the outputs which can't be spent in the block after the last
+    /// Wallet.sync are returned, the same as the ones counted in Balance.immature, and all of them
+    /// if the wallet was never synced. Note that this method only operates on the internal
+    /// database, which first needs to be Wallet.sync manually.
+    pub(crate) fn list_immature_utxos(
+        &self,
+        current_height: Option<u32>,
+    ) -> Result<Vec<ImmatureUtxo>, BdkError> {
+        let wallet = self.get_wallet();
+        let current_height = match current_height {
+            Some(current_height) => current_height,
+            None => last_sync_height(&wallet)?.map_or(0, |height| height + 1),
+        };
+        let mut immature_utxos = Vec::new();
+        for utxo in wallet.list_unspent()? {
+            let tx_details = match wallet.get_tx(&utxo.outpoint.txid, true)? {
+                Some(tx_details) => tx_details,
+                None => continue,
+            };
+            let is_coinbase = tx_details
+                .transaction
+                .as_ref()
+                .map_or(false, |tx| tx.is_coin_base());
+            if let (true, Some(confirmation_time)) = (is_coinbase, tx_details.confirmation_time) {
+                let maturity_height = confirmation_time.height + COINBASE_MATURITY;
+                if current_height < maturity_height {
+                    immature_utxos.push(ImmatureUtxo {
+                        utxo: LocalUtxo {
+                            confirmation_height: Some(confirmation_time.height),
+                            ..LocalUtxo::from(utxo)
+                        },
+                        maturity_height,
+                    });
+                }
+            }
+        }
+        Ok(immature_utxos)
+    }
 
-    /// Whether to try finalizing the PSBT after the inputs are signed.
-    ///
-    /// Defaults to `true` which will try finalizing PSBT after inputs are signed.
-    pub try_finalize: bool,
+    /// The height of the chain tip the wallet saw in its last Wallet.sync, or None if it was
+    /// never synced, e.g. to compute the number of confirmations of a transaction without asking
+    /// the blockchain backend. The height isn't updated until the next sync, so it can be behind
+    /// the actual chain tip.
+    pub(crate) fn get_last_synced_height(&self) -> Result<Option<u32>, BdkError> {
+        last_sync_height(&self.get_wallet())
+    }
 
-    // Specifies which Taproot script-spend leaves we should sign for. This option is
-    // ignored if we're signing a non-taproot PSBT.
-    //
-    // Defaults to All, i.e., the wallet will sign all the leaves it has a key for.
-    // TODO pub tap_leaves_options: TapLeavesOptions,
-    /// Whether we should try to sign a taproot transaction with the taproot internal key
-    /// or not. This option is ignored if we're signing a non-taproot PSBT.
-    ///
-    /// Defaults to `true`, i.e., we always try to sign with the taproot internal key.
-    pub sign_with_tap_internal_key: bool,
+    /// Return the spending policy of the given keychain's descriptor as a JSON string, or None if
+    /// the descriptor has no spending policy. The policy describes the conditions to spend from the
+    /// wallet, e.g. the threshold and key fingerprints of a multisig or any timelocks, and can be
+    /// used to drive a cosigner UI.
+    pub(crate) fn policies(&self, keychain: KeychainKind) -> Result<Option<String>, BdkError> {
+        let policy = self.get_wallet().policies(keychain)?;
+        policy
+            .map(|policy| serde_json::to_string(&policy).map_err(BdkError::from))
+            .transpose()
+    }
 
-    /// Whether we should grind ECDSA signature to ensure signing with low r
-    /// or not.
-    /// Defaults to `true`, i.e., we always grind ECDSA signature to sign with low r.
-    pub allow_grinding: bool,
-}
+    /// Return the wallet's unspent output for the given outpoint, or None if the outpoint isn't a
+    /// wallet UTXO. Note that this method only operates on the internal database, which first
+    /// needs to be Wallet.sync manually.
+    pub(crate) fn get_utxo(&self, outpoint: OutPoint) -> Result<Option<LocalUtxo>, BdkError> {
+        let outpoint = BdkOutPoint {
+            txid: Txid::from_str(&outpoint.txid).map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?,
+            vout: outpoint.vout,
+        };
+        let wallet = self.get_wallet();
+        let utxo = wallet.get_utxo(outpoint)?;
+        utxo.map(|utxo| local_utxo(&wallet, utxo)).transpose()
+    }
 
-impl From<SignOptions> for BdkSignOptions {
-    fn from(sign_options: SignOptions) -> Self {
-        BdkSignOptions {
-            trust_witness_utxo: sign_options.trust_witness_utxo,
-            assume_height: sign_options.assume_height,
-            allow_all_sighashes: sign_options.allow_all_sighashes,
-            remove_partial_sigs: sign_options.remove_partial_sigs,
-            try_finalize: sign_options.try_finalize,
-            tap_leaves_options: Default::default(),
-            sign_with_tap_internal_key: sign_options.sign_with_tap_internal_key,
-            allow_grinding: sign_options.allow_grinding,
+    /// Force the wallet database to persist its pending writes to disk. Sled databases buffer
+    /// writes and only flush them periodically, so apps should call this from lifecycle hooks
+    /// (e.g. when backgrounded) to avoid losing recently synced state. This does nothing for the
+    /// memory and SQLite databases.
+    pub(crate) fn flush(&self) -> Result<(), BdkError> {
+        let wallet = self.get_wallet();
+        if let AnyDatabase::Sled(tree) = wallet.database().deref() {
+            tree.flush()?;
         }
+        Ok(())
     }
-}
-
-/// A transaction builder.
-/// After creating the TxBuilder, you set options on it until finally calling finish to consume the builder and generate the transaction.
-/// Each method on the TxBuilder returns an instance of a new TxBuilder with the option set/added.
-#[derive(Clone, Debug)]
-pub(crate) struct TxBuilder {
-    pub(crate) recipients: Vec<(BdkScript, u64)>,
-    pub(crate) utxos: Vec<OutPoint>,
-    pub(crate) unspendable: HashSet<OutPoint>,
-    pub(crate) change_policy: ChangeSpendPolicy,
-    pub(crate) manually_selected_only: bool,
-    pub(crate) fee_rate: Option<f32>,
-    pub(crate) fee_absolute: Option<u64>,
-    pub(crate) drain_wallet: bool,
-    pub(crate) drain_to: Option<BdkScript>,
-    pub(crate) rbf: Option<RbfValue>,
-    pub(crate) data: Vec<u8>,
-}
 
-impl TxBuilder {
-    pub(crate) fn new() -> Self {
-        TxBuilder {
-            recipients: Vec::new(),
-            utxos: Vec::new(),
-            unspendable: HashSet::new(),
-            change_policy: ChangeSpendPolicy::ChangeAllowed,
-            manually_selected_only: false,
-            fee_rate: None,
-            fee_absolute: None,
-            drain_wallet: false,
-            drain_to: None,
-            rbf: None,
-            data: Vec::new(),
-        }
+    /// Return the list of unspent outputs of this wallet belonging to the given keychain, e.g. only
+    /// the change outputs. Note that this method only operates on the internal database, which
+    /// first needs to be Wallet.sync manually.
+    pub(crate) fn list_unspent_for_keychain(
+        &self,
+        keychain: KeychainKind,
+    ) -> Result<Vec<LocalUtxo>, BdkError> {
+        let wallet = self.get_wallet();
+        let unspents: Vec<BdkLocalUtxo> = wallet.list_unspent()?;
+        unspents
+            .into_iter()
+            .filter(|utxo| utxo.keychain == keychain)
+            .map(|utxo| local_utxo(&wallet, utxo))
+            .collect()
     }
 
-    /// Add a recipient to the internal list.
-    pub(crate) fn add_recipient(&self, script: Arc<Script>, amount: u64) -> Arc<Self> {
-        let mut recipients: Vec<(BdkScript, u64)> = self.recipients.clone();
-        recipients.append(&mut vec![(script.script.clone(), amount)]);
-        Arc::new(TxBuilder {
-            recipients,
-            ..self.clone()
-        })
+    /// Build a transaction that sends all the wallet's funds to a single address. This is a shortcut
+    /// for a TxBuilder with both drain_wallet and drain_to set, paying the given fee rate (sat/vB).
+    pub(crate) fn sweep(
+        &self,
+        address: Arc<Address>,
+        fee_rate: f32,
+    ) -> Result<TxBuilderResult, BdkError> {
+        TxBuilder::new()
+            .fee_rate(fee_rate)
+            .drain_wallet()
+            .drain_to(address.script_pubkey())
+            .finish(self)
     }
 
-    pub(crate) fn set_recipients(&self, recipients: Vec<ScriptAmount>) -> Arc<Self> {
-        let recipients = recipients
+    /// For each output of the PSBT, return whether it belongs to this wallet and the derivation path
+    /// found in the PSBT output's BIP32 derivation fields (if any). This is the information needed to
+    /// verify change outputs on a hardware wallet display.
+    pub(crate) fn get_psbt_outputs_info(
+        &self,
+        psbt: &PartiallySignedTransaction,
+    ) -> Result<Vec<PsbtOutputInfo>, BdkError> {
+        let psbt = psbt.internal.lock().unwrap();
+        let wallet = self.get_wallet();
+        psbt.unsigned_tx
+            .output
             .iter()
-            .map(|script_amount| (script_amount.script.script.clone(), script_amount.amount))
-            .collect();
-        Arc::new(TxBuilder {
-            recipients,
-            ..self.clone()
-        })
+            .zip(psbt.outputs.iter())
+            .map(|(tx_out, psbt_output)| {
+                let is_mine = wallet.is_mine(&tx_out.script_pubkey)?;
+                let derivation_path = psbt_output
+                    .bip32_derivation
+                    .values()
+                    .map(|(_, path)| path)
+                    .chain(
+                        psbt_output
+                            .tap_key_origins
+                            .values()
+                            .map(|(_, (_, path))| path),
+                    )
+                    .next()
+                    .map(|path| path.to_string());
+                Ok(PsbtOutputInfo {
+                    is_mine,
+                    derivation_path,
+                })
+            })
+            .collect()
     }
 
-    /// Add a utxo to the internal list of unspendable utxos. It’s important to note that the "must-be-spent"
-    /// utxos added with [TxBuilder.addUtxo] have priority over this. See the Rust docs of the two linked methods for more details.
-    pub(crate) fn add_unspendable(&self, unspendable: OutPoint) -> Arc<Self> {
-        let mut unspendable_hash_set = self.unspendable.clone();
-        unspendable_hash_set.insert(unspendable);
-        Arc::new(TxBuilder {
-            unspendable: unspendable_hash_set,
-            ..self.clone()
-        })
+    /// Return the outputs of one of the wallet's transactions with their address, amount and
+    /// whether they belong to the wallet. This is the per-output breakdown of the sent and received
+    /// amounts of TransactionDetails. Outputs without an address form (e.g. OP_RETURN) have no
+    /// address.
+    pub(crate) fn get_tx_outputs(&self, txid: String) -> Result<Vec<TxOutputInfo>, BdkError> {
+        let txid = Txid::from_str(txid.as_str()).map_err(|e| BdkError::Generic {
+            error_message: e.to_string(),
+        })?;
+        let wallet = self.get_wallet();
+        let tx = wallet
+            .get_tx(&txid, true)?
+            .and_then(|tx_details| tx_details.transaction)
+            .ok_or(bdk::Error::TransactionNotFound)?;
+        tx.output
+            .iter()
+            .map(|tx_out| {
+                Ok(TxOutputInfo {
+                    address: BdkAddress::from_script(&tx_out.script_pubkey, wallet.network())
+                        .ok()
+                        .map(|address| address.to_string()),
+                    amount: tx_out.value,
+                    is_mine: wallet.is_mine(&tx_out.script_pubkey)?,
+                })
+            })
+            .collect()
     }
 
-    /// Add an outpoint to the internal list of UTXOs that must be spent. These have priority over the "unspendable"
-    /// utxos, meaning that if a utxo is present both in the "utxos" and the "unspendable" list, it will be spent.
-    pub(crate) fn add_utxo(&self, outpoint: OutPoint) -> Arc<Self> {
-        self.add_utxos(vec![outpoint])
+    /// Return the inputs of one of the wallet's transactions with the outpoint they spend and,
+    /// when the spent transaction is known to the wallet, its amount and address. This complements
+    /// get_tx_outputs, e.g. for the inputs section of a transaction detail screen.
+    pub(crate) fn get_tx_inputs(&self, txid: String) -> Result<Vec<TxInputInfo>, BdkError> {
+        let txid = Txid::from_str(txid.as_str()).map_err(|e| BdkError::Generic {
+            error_message: e.to_string(),
+        })?;
+        let wallet = self.get_wallet();
+        let tx = wallet
+            .get_tx(&txid, true)?
+            .and_then(|tx_details| tx_details.transaction)
+            .ok_or(bdk::Error::TransactionNotFound)?;
+        let database = wallet.database();
+        tx.input
+            .iter()
+            .map(|input| {
+                let previous_output = database
+                    .get_raw_tx(&input.previous_output.txid)?
+                    .and_then(|tx| tx.output.get(input.previous_output.vout as usize).cloned());
+                Ok(TxInputInfo {
+                    outpoint: OutPoint::from(&input.previous_output),
+                    amount: previous_output.as_ref().map(|tx_out| tx_out.value),
+                    address: previous_output.and_then(|tx_out| {
+                        BdkAddress::from_script(&tx_out.script_pubkey, wallet.network())
+                            .ok()
+                            .map(|address| address.to_string())
+                    }),
+                })
+            })
+            .collect()
     }
 
-    /// Add the list of outpoints to the internal list of UTXOs that must be spent. If an error occurs while adding
-    /// any of the UTXOs then none of them are added and the error is returned. These have priority over the "unspendable"
-    /// utxos, meaning that if a utxo is present both in the "utxos" and the "unspendable" list, it will be spent.
-    pub(crate) fn add_utxos(&self, mut outpoints: Vec<OutPoint>) -> Arc<Self> {
-        let mut utxos = self.utxos.to_vec();
-        utxos.append(&mut outpoints);
-        Arc::new(TxBuilder {
-            utxos,
-            ..self.clone()
-        })
+    /// Calculate the fee paid by a transaction, ours or a third party's. All the previous
+    /// transactions spent by it must be known to the wallet, otherwise an error is returned.
+    pub(crate) fn calculate_fee(&self, tx: Arc<Transaction>) -> Result<u64, BdkError> {
+        let wallet = self.get_wallet();
+        let database = wallet.database();
+        let output_value = tx
+            .internal
+            .output
+            .iter()
+            .try_fold(0, |total, tx_out| add_value(total, tx_out.value))?;
+        let mut input_value = 0;
+        for input in &tx.internal.input {
+            let previous_output = database
+                .get_raw_tx(&input.previous_output.txid)?
+                .and_then(|tx| tx.output.get(input.previous_output.vout as usize).cloned())
+                .ok_or_else(|| BdkError::Generic {
+                    error_message: format!(
+                        "Can't calculate the fee, previous output {} is not known to the wallet",
+                        input.previous_output
+                    ),
+                })?;
+            input_value = add_value(input_value, previous_output.value)?;
+        }
+        input_value
+            .checked_sub(output_value)
+            .ok_or_else(|| BdkError::Generic {
+                error_message: "Transaction outputs are greater than its inputs".to_string(),
+            })
     }
 
-    /// Do not spend change outputs. This effectively adds all the change outputs to the "unspendable" list. See TxBuilder.unspendable.
-    pub(crate) fn do_not_spend_change(&self) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            change_policy: ChangeSpendPolicy::ChangeForbidden,
-            ..self.clone()
-        })
+    /// Calculate the fee rate paid by a transaction, see calculate_fee. The fee rate is only
+    /// accurate for transactions that are fully signed.
+    pub(crate) fn calculate_fee_rate(
+        &self,
+        tx: Arc<Transaction>,
+    ) -> Result<Arc<crate::FeeRate>, BdkError> {
+        let weight = tx.internal.weight();
+        let fee = self.calculate_fee(tx)?;
+        Ok(Arc::new(FeeRate::from_wu(fee, weight).into()))
     }
 
-    /// Only spend utxos added by [add_utxo]. The wallet will not add additional utxos to the transaction even if they are
-    /// needed to make the transaction valid.
-    pub(crate) fn manually_selected_only(&self) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            manually_selected_only: true,
-            ..self.clone()
+    /// Insert a transaction and its confirmation time into the wallet database without syncing,
+    /// e.g. for transactions received out-of-band. Outputs paying to the wallet's cached scripts
+    /// become UTXOs and wallet UTXOs spent by the transaction are marked as spent. The fee is only
+    /// set if all the previous transactions are already known to the wallet.
+    pub(crate) fn insert_tx(
+        &self,
+        transaction: Arc<Transaction>,
+        confirmation_time: Option<BlockTime>,
+    ) -> Result<(), BdkError> {
+        self.update_database(InsertTx {
+            tx: transaction.internal.clone(),
+            confirmation_time,
         })
     }
 
-    /// Only spend change outputs. This effectively adds all the non-change outputs to the "unspendable" list. See TxBuilder.unspendable.
-    pub(crate) fn only_spend_change(&self) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            change_policy: ChangeSpendPolicy::OnlyChange,
-            ..self.clone()
-        })
+    /// Return the highest index of the keychain whose address received a transaction output, or
+    /// None if none was used yet. Unlike the revealed index, this only counts addresses which were
+    /// actually used, e.g. to check how close a restored wallet is to its gap limit. Note that this
+    /// method only operates on the internal database, which first needs to be Wallet.sync
+    /// manually.
+    pub(crate) fn get_last_used_index(
+        &self,
+        keychain: KeychainKind,
+    ) -> Result<Option<u32>, BdkError> {
+        let used_indexes = used_indexes(&self.get_wallet(), keychain)?;
+        Ok(used_indexes.into_iter().max())
     }
 
-    /// Replace the internal list of unspendable utxos with a new list. It’s important to note that the "must-be-spent" utxos added with
-    /// TxBuilder.addUtxo have priority over these. See the Rust docs of the two linked methods for more details.
-    pub(crate) fn unspendable(&self, unspendable: Vec<OutPoint>) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            unspendable: unspendable.into_iter().collect(),
-            ..self.clone()
-        })
+    /// Return the next address of the keychain that was neither handed out before nor used, e.g.
+    /// by another wallet app sharing the descriptor. Unlike AddressIndex.LastUnused, the address
+    /// is revealed so it's never returned twice, even to concurrent callers. Note that this method
+    /// only operates on the internal database, which first needs to be Wallet.sync manually.
+    pub(crate) fn next_unused_address(
+        &self,
+        keychain: KeychainKind,
+    ) -> Result<AddressInfo, BdkError> {
+        let wallet = self.get_wallet();
+        let used_indexes = used_indexes(&wallet, keychain)?;
+        let mut previous_index = None;
+        loop {
+            let address_info = match keychain {
+                KeychainKind::External => wallet.get_address(AddressIndex::New.into())?,
+                KeychainKind::Internal => wallet.get_internal_address(AddressIndex::New.into())?,
+            };
+            // descriptors without a wildcard always return the same address
+            if !used_indexes.contains(&address_info.index)
+                || previous_index == Some(address_info.index)
+            {
+                return Ok(AddressInfo::from(address_info));
+            }
+            previous_index = Some(address_info.index);
+        }
     }
 
-    /// Set a custom fee rate.
-    pub(crate) fn fee_rate(&self, sat_per_vb: f32) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            fee_rate: Some(sat_per_vb),
-            ..self.clone()
-        })
+    /// Export the wallet as a JSON backup with its network, its descriptors including their
+    /// private keys, and the revealed index of each keychain, which Wallet.import_backup restores.
+    /// The backup holds the private keys of the wallet, so it must be stored as securely as a seed.
+    /// Signers added with Wallet.add_signer aren't part of the backup.
+    pub(crate) fn export_backup(&self) -> Result<String, BdkError> {
+        let wallet = self.get_wallet();
+        let mut descriptors = Vec::new();
+        let mut last_indexes = Vec::new();
+        for keychain in &[KeychainKind::External, KeychainKind::Internal] {
+            let descriptor = wallet.public_descriptor(*keychain)?.map(|descriptor| {
+                let key_map = wallet.get_signers(*keychain).as_key_map(wallet.secp_ctx());
+                descriptor.to_string_with_secret(&key_map)
+            });
+            descriptors.push(descriptor);
+            last_indexes.push(wallet.database().get_last_index(*keychain)?);
+        }
+        let backup = serde_json::json!({
+            "version": BACKUP_VERSION,
+            "network": wallet.network().to_string(),
+            "descriptor": descriptors[0],
+            "change_descriptor": descriptors[1],
+            "external_index": last_indexes[0],
+            "internal_index": last_indexes[1],
+        });
+        Ok(backup.to_string())
     }
 
-    /// Set an absolute fee.
-    pub(crate) fn fee_absolute(&self, fee_amount: u64) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            fee_absolute: Some(fee_amount),
-            ..self.clone()
-        })
-    }
+    /// Create a wallet from a backup made with Wallet.export_backup, revealing the addresses up
+    /// to the indexes of the backup so they aren't handed out again.
+    pub(crate) fn import_backup(
+        backup: String,
+        database_config: DatabaseConfig,
+    ) -> Result<Self, BdkError> {
+        let backup: serde_json::Value = serde_json::from_str(&backup)?;
+        let invalid_backup = |field: &str| BdkError::Generic {
+            error_message: format!("Invalid backup {}", field),
+        };
+        if backup["version"].as_u64() != Some(BACKUP_VERSION) {
+            return Err(invalid_backup("version"));
+        }
+        let network = backup["network"]
+            .as_str()
+            .and_then(|network| Network::from_str(network).ok())
+            .ok_or_else(|| invalid_backup("network"))?;
+        let descriptor = backup["descriptor"]
+            .as_str()
+            .ok_or_else(|| invalid_backup("descriptor"))?;
+        let change_descriptor = match &backup["change_descriptor"] {
+            serde_json::Value::Null => None,
+            change_descriptor => Some(
+                change_descriptor
+                    .as_str()
+                    .ok_or_else(|| invalid_backup("change descriptor"))?,
+            ),
+        };
+        let index = |field: &str| -> Result<Option<u32>, BdkError> {
+            match &backup[field] {
+                serde_json::Value::Null => Ok(None),
+                index => index
+                    .as_u64()
+                    .and_then(|index| u32::try_from(index).ok())
+                    .map(Some)
+                    .ok_or_else(|| invalid_backup(field)),
+            }
+        };
+        let external_index = index("external_index")?;
+        let internal_index = index("internal_index")?;
 
-    /// Spend all the available inputs. This respects filters like TxBuilder.unspendable and the change policy.
-    pub(crate) fn drain_wallet(&self) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            drain_wallet: true,
-            ..self.clone()
-        })
+        let wallet = Wallet::new(
+            Arc::new(Descriptor::new(descriptor.to_string(), network)?),
+            change_descriptor
+                .map(|descriptor| Descriptor::new(descriptor.to_string(), network))
+                .transpose()?
+                .map(Arc::new),
+            network,
+            database_config,
+        )?;
+        // the indexes are the ones stored by the database, wallets without a change descriptor
+        // store the index of their change addresses under the external keychain
+        for (keychain, index) in [
+            (KeychainKind::External, external_index),
+            (KeychainKind::Internal, internal_index),
+        ] {
+            if let Some(index) = index {
+                wallet.update_database(RevealTo { keychain, index })?;
+            }
+        }
+        Ok(wallet)
     }
 
-    /// Sets the address to drain excess coins to. Usually, when there are excess coins they are sent to a change address
-    /// generated by the wallet. This option replaces the usual change address with an arbitrary ScriptPubKey of your choosing.
-    /// Just as with a change output, if the drain output is not needed (the excess coins are too small) it will not be included
-    /// in the resulting transaction. The only difference is that it is valid to use drain_to without setting any ordinary recipients
-    /// with add_recipient (but it is perfectly fine to add recipients as well). If you choose not to set any recipients, you should
-    /// either provide the utxos that the transaction should spend via add_utxos, or set drain_wallet to spend all of them.
-    /// When bumping the fees of a transaction made with this option, you probably want to use BumpFeeTxBuilder.allow_shrinking
-    /// to allow this output to be reduced to pay for the extra fees.
-    pub(crate) fn drain_to(&self, script: Arc<Script>) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            drain_to: Some(script.script.clone()),
-            ..self.clone()
-        })
+    /// Advance the revealed address index of a keychain to at least `index`, e.g. after restoring a
+    /// wallet whose addresses were already used up to that index, so AddressIndex.LastUnused
+    /// doesn't return an already used address. The revealed index never goes backwards.
+    pub(crate) fn reveal_to(&self, keychain: KeychainKind, index: u32) -> Result<(), BdkError> {
+        // wallets without a change descriptor use the external keychain for change addresses
+        let keychain = match self.get_wallet().public_descriptor(keychain)? {
+            Some(_) => keychain,
+            None => KeychainKind::External,
+        };
+        self.update_database(RevealTo { keychain, index })
     }
 
-    /// Enable signaling RBF. This will use the default `nsequence` value of `0xFFFFFFFD`.
-    pub(crate) fn enable_rbf(&self) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            rbf: Some(RbfValue::Default),
-            ..self.clone()
-        })
+    /// Remove all the transactions and UTXOs from the wallet database, e.g. when it got into a bad
+    /// state or the stop gap used to restore the wallet was too small, so the next Wallet.sync
+    /// rescans the wallet from scratch. The revealed address indices are kept, so the rescan
+    /// covers at least the addresses handed out so far. Transactions which are neither confirmed
+    /// nor in the mempool of the backend, e.g. added with Wallet.insert_tx or not broadcast yet,
+    /// are lost and their inputs count as unspent again.
+    pub(crate) fn clear_cache(&self) -> Result<(), BdkError> {
+        self.update_database(ClearChainData)
     }
 
-    /// Enable signaling RBF with a specific nSequence value. This can cause conflicts if the wallet's descriptors contain an
-    /// "older" (OP_CSV) operator and the given `nsequence` is lower than the CSV value. If the `nsequence` is higher than `0xFFFFFFFD`
-    /// an error will be thrown, since it would not be a valid nSequence to signal RBF.
-    pub(crate) fn enable_rbf_with_sequence(&self, nsequence: u32) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            rbf: Some(RbfValue::Value(nsequence)),
-            ..self.clone()
-        })
+    /// Apply a change to the wallet database outside of a regular blockchain sync.
+    fn update_database<U: DatabaseUpdate>(&self, update: U) -> Result<(), BdkError> {
+        apply_database_update(&self.get_wallet(), update)
     }
+}
 
-    /// Add data as an output using OP_RETURN.
-    pub(crate) fn add_data(&self, data: Vec<u8>) -> Arc<Self> {
-        Arc::new(TxBuilder {
-            data,
-            ..self.clone()
-        })
+/// Sync the wallet with the locked backend of `blockchain`.
+fn sync_wallet(
+    wallet: &BdkWallet<AnyDatabase>,
+    blockchain: &Blockchain,
+    any_blockchain: &AnyBlockchain,
+    progress: Option<Box<dyn Progress>>,
+) -> Result<(), BdkError> {
+    blockchain.check_network(any_blockchain, wallet.network())?;
+    let bdk_sync_opts = BdkSyncOptions {
+        progress: progress.map(|p| {
+            Box::new(ProgressHolder { progress: p })
+                as Box<(dyn bdk::blockchain::Progress + 'static)>
+        }),
+    };
+
+    info!("Syncing wallet");
+    let result = wallet
+        .sync(any_blockchain, bdk_sync_opts)
+        .map_err(BdkError::from);
+    match &result {
+        Ok(()) => info!("Wallet synced"),
+        Err(e) => error!("Wallet sync failed: {}", e),
     }
+    result
+}
 
-    /// Finish building the transaction. Returns the BIP174 PSBT.
-    pub(crate) fn finish(&self, wallet: &Wallet) -> Result<TxBuilderResult, BdkError> {
-        let wallet = wallet.get_wallet();
-        let mut tx_builder = wallet.build_tx();
-        for (script, amount) in &self.recipients {
-            tx_builder.add_recipient(script.clone(), *amount);
-        }
-        tx_builder.change_policy(self.change_policy);
-        if !self.utxos.is_empty() {
-            let bdk_utxos: Vec<BdkOutPoint> = self.utxos.iter().map(BdkOutPoint::from).collect();
-            let utxos: &[BdkOutPoint] = &bdk_utxos;
-            tx_builder.add_utxos(utxos)?;
-        }
-        if !self.unspendable.is_empty() {
-            let bdk_unspendable: Vec<BdkOutPoint> =
-                self.unspendable.iter().map(BdkOutPoint::from).collect();
-            tx_builder.unspendable(bdk_unspendable);
-        }
-        if self.manually_selected_only {
-            tx_builder.manually_selected_only();
-        }
-        if let Some(sat_per_vb) = self.fee_rate {
-            tx_builder.fee_rate(FeeRate::from_sat_per_vb(sat_per_vb));
-        }
-        if let Some(fee_amount) = self.fee_absolute {
-            tx_builder.fee_absolute(fee_amount);
+/// The height of the chain tip recorded by the last sync of the wallet, if it was ever synced.
+fn last_sync_height(wallet: &BdkWallet<AnyDatabase>) -> Result<Option<u32>, BdkError> {
+    Ok(wallet
+        .database()
+        .get_sync_time()?
+        .map(|sync_time| sync_time.block_time.height))
+}
+
+fn apply_database_update<U: DatabaseUpdate>(
+    wallet: &BdkWallet<AnyDatabase>,
+    update: U,
+) -> Result<(), BdkError> {
+    let updater = DatabaseUpdater {
+        update,
+        applied: Cell::new(false),
+    };
+    match wallet.sync(&updater, BdkSyncOptions::default()) {
+        // the sync is stopped on purpose once the update is applied, see DatabaseUpdater. This
+        // relies on bdk 0.27 calling get_height after wallet_setup and returning its error as is,
+        // so it must be revisited on any bdk upgrade.
+        Err(bdk::Error::Generic(message))
+            if message == DATABASE_UPDATE_STOPPED && updater.applied.get() =>
+        {
+            Ok(())
         }
-        if self.drain_wallet {
-            tx_builder.drain_wallet();
+        result => Ok(result?),
+    }
+}
+
+/// The error DatabaseUpdater returns to stop the sync once the update is applied.
+const DATABASE_UPDATE_STOPPED: &str = "A database update has no chain height";
+
+/// A change to the wallet database which doesn't come from a blockchain backend.
+trait DatabaseUpdate {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error>;
+}
+
+/// BDK only hands out mutable access to the wallet database to a blockchain backend during a sync,
+/// so database changes which don't come from a blockchain are applied by syncing with this offline
+/// "blockchain". BDK records the sync time at the chain height once the sync is done, so the
+/// height can't be fetched and the sync stops there, leaving the sync time of the last actual sync
+/// as is.
+struct DatabaseUpdater<U> {
+    update: U,
+    applied: Cell<bool>,
+}
+
+impl<U: DatabaseUpdate> WalletSync for DatabaseUpdater<U> {
+    fn wallet_setup<D: BatchDatabase>(
+        &self,
+        database: &RefCell<D>,
+        _progress_update: Box<dyn BdkProgress>,
+    ) -> Result<(), bdk::Error> {
+        self.update.apply(database.borrow_mut().deref_mut())?;
+        self.applied.set(true);
+        Ok(())
+    }
+}
+
+impl<U> GetHeight for DatabaseUpdater<U> {
+    fn get_height(&self) -> Result<u32, bdk::Error> {
+        Err(bdk::Error::Generic(DATABASE_UPDATE_STOPPED.to_string()))
+    }
+}
+
+/// Add an amount to a total of transaction amounts, which can only overflow for a crafted
+/// transaction.
+fn add_value(total: u64, value: u64) -> Result<u64, bdk::Error> {
+    total
+        .checked_add(value)
+        .ok_or_else(|| bdk::Error::Generic("Transaction amounts overflow".to_string()))
+}
+
+struct InsertTx {
+    tx: BdkTransaction,
+    confirmation_time: Option<BlockTime>,
+}
+
+impl DatabaseUpdate for InsertTx {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error> {
+        let txid = self.tx.txid();
+        // the amounts are checked before anything is written, a crafted transaction can overflow
+        let output_value = self
+            .tx
+            .output
+            .iter()
+            .try_fold(0, |total, tx_out| add_value(total, tx_out.value))?;
+
+        let mut sent = 0;
+        // None as soon as one of the previous outputs is unknown
+        let mut input_value = Some(0_u64);
+        for input in &self.tx.input {
+            let previous_output = database
+                .get_raw_tx(&input.previous_output.txid)?
+                .and_then(|tx| tx.output.get(input.previous_output.vout as usize).cloned());
+            match previous_output {
+                Some(tx_out) => {
+                    input_value = input_value
+                        .map(|value| add_value(value, tx_out.value))
+                        .transpose()?;
+                    if database
+                        .get_path_from_script_pubkey(&tx_out.script_pubkey)?
+                        .is_some()
+                    {
+                        sent = add_value(sent, tx_out.value)?;
+                    }
+                }
+                None => input_value = None,
+            }
         }
-        if let Some(script) = &self.drain_to {
-            tx_builder.drain_to(script.clone());
+        for input in &self.tx.input {
+            if let Some(mut utxo) = database.get_utxo(&input.previous_output)? {
+                utxo.is_spent = true;
+                database.set_utxo(&utxo)?;
+            }
         }
-        if let Some(rbf) = &self.rbf {
-            match *rbf {
-                RbfValue::Default => {
-                    tx_builder.enable_rbf();
-                }
-                RbfValue::Value(nsequence) => {
-                    tx_builder.enable_rbf_with_sequence(Sequence(nsequence));
+
+        let mut received = 0;
+        for (vout, tx_out) in self.tx.output.iter().enumerate() {
+            if let Some((keychain, _)) =
+                database.get_path_from_script_pubkey(&tx_out.script_pubkey)?
+            {
+                received = add_value(received, tx_out.value)?;
+                let outpoint = BdkOutPoint::new(txid, vout as u32);
+                // don't mark an output that was already spent as unspent again
+                if database.get_utxo(&outpoint)?.is_none() {
+                    database.set_utxo(&BdkLocalUtxo {
+                        outpoint,
+                        txout: tx_out.clone(),
+                        keychain,
+                        is_spent: false,
+                    })?;
                 }
             }
         }
-        if !&self.data.is_empty() {
-            tx_builder.add_data(self.data.as_slice());
-        }
 
-        tx_builder
-            .finish()
-            .map(|(psbt, tx_details)| TxBuilderResult {
-                psbt: Arc::new(PartiallySignedTransaction {
-                    internal: Mutex::new(psbt),
-                }),
-                transaction_details: TransactionDetails::from(tx_details),
-            })
+        database.set_tx(&BdkTransactionDetails {
+            transaction: Some(self.tx.clone()),
+            txid,
+            received,
+            sent,
+            fee: input_value.and_then(|value| value.checked_sub(output_value)),
+            confirmation_time: self.confirmation_time.clone(),
+        })
     }
 }
 
-/// The BumpFeeTxBuilder is used to bump the fee on a transaction that has been broadcast and has its RBF flag set to true.
-#[derive(Clone)]
-pub(crate) struct BumpFeeTxBuilder {
-    pub(crate) txid: String,
-    pub(crate) fee_rate: f32,
-    pub(crate) allow_shrinking: Option<String>,
-    pub(crate) rbf: Option<RbfValue>,
+/// Saves transactions in the database without adding them to the wallet transactions, e.g. the
+/// previous transactions of inputs which don't belong to the wallet.
+struct InsertRawTxs {
+    txs: Vec<BdkTransaction>,
 }
 
-impl BumpFeeTxBuilder {
-    pub(crate) fn new(txid: String, fee_rate: f32) -> Self {
-        Self {
-            txid,
-            fee_rate,
-            allow_shrinking: None,
-            rbf: None,
+impl DatabaseUpdate for InsertRawTxs {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error> {
+        for tx in &self.txs {
+            database.set_raw_tx(tx)?;
         }
+        Ok(())
     }
+}
 
-    /// Explicitly tells the wallet that it is allowed to reduce the amount of the output matching this script_pubkey
-    /// in order to bump the transaction fee. Without specifying this the wallet will attempt to find a change output to
-    /// shrink instead. Note that the output may shrink to below the dust limit and therefore be removed. If it is preserved
-    /// then it is currently not guaranteed to be in the same position as it was originally. Returns an error if script_pubkey
-    /// can’t be found among the recipients of the transaction we are bumping.
-    pub(crate) fn allow_shrinking(&self, address: String) -> Arc<Self> {
-        Arc::new(Self {
-            allow_shrinking: Some(address),
-            ..self.clone()
-        })
-    }
-
-    /// Enable signaling RBF. This will use the default `nsequence` value of `0xFFFFFFFD`.
-    pub(crate) fn enable_rbf(&self) -> Arc<Self> {
-        Arc::new(Self {
-            rbf: Some(RbfValue::Default),
-            ..self.clone()
-        })
-    }
+/// The database entries an insert of a transaction and of its previous transactions can change,
+/// as they were before the insert. Applying them restores these entries, deleting the ones that
+/// didn't exist.
+struct DatabaseEntries {
+    txs: Vec<(Txid, Option<BdkTransactionDetails>)>,
+    raw_txs: Vec<(Txid, Option<BdkTransaction>)>,
+    utxos: Vec<(BdkOutPoint, Option<BdkLocalUtxo>)>,
+}
 
-    /// Enable signaling RBF with a specific nSequence value. This can cause conflicts if the wallet's descriptors contain an
-    /// "older" (OP_CSV) operator and the given `nsequence` is lower than the CSV value. If the `nsequence` is higher than `0xFFFFFFFD`
-    /// an error will be thrown, since it would not be a valid nSequence to signal RBF.
-    pub(crate) fn enable_rbf_with_sequence(&self, nsequence: u32) -> Arc<Self> {
-        Arc::new(Self {
-            rbf: Some(RbfValue::Value(nsequence)),
-            ..self.clone()
+impl DatabaseEntries {
+    fn new<D: Database>(
+        database: &D,
+        tx: &BdkTransaction,
+        previous_txs: &[BdkTransaction],
+    ) -> Result<Self, bdk::Error> {
+        let txid = tx.txid();
+        let raw_txs = previous_txs
+            .iter()
+            .map(BdkTransaction::txid)
+            .chain(std::iter::once(txid))
+            .map(|txid| Ok((txid, database.get_raw_tx(&txid)?)))
+            .collect::<Result<_, bdk::Error>>()?;
+        let utxos = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output)
+            .chain((0..tx.output.len() as u32).map(|vout| BdkOutPoint::new(txid, vout)))
+            .map(|outpoint| Ok((outpoint, database.get_utxo(&outpoint)?)))
+            .collect::<Result<_, bdk::Error>>()?;
+        Ok(DatabaseEntries {
+            txs: vec![(txid, database.get_tx(&txid, false)?)],
+            raw_txs,
+            utxos,
         })
     }
+}
 
-    /// Finish building the transaction. Returns the BIP174 PSBT.
-    pub(crate) fn finish(
-        &self,
-        wallet: &Wallet,
-    ) -> Result<Arc<PartiallySignedTransaction>, BdkError> {
-        let wallet = wallet.get_wallet();
-        let txid = Txid::from_str(self.txid.as_str())?;
-        let mut tx_builder = wallet.build_fee_bump(txid)?;
-        tx_builder.fee_rate(FeeRate::from_sat_per_vb(self.fee_rate));
-        if let Some(allow_shrinking) = &self.allow_shrinking {
-            let address = BdkAddress::from_str(allow_shrinking)
-                .map_err(|e| BdkError::Generic(e.to_string()))?;
-            let script = address.script_pubkey();
-            tx_builder.allow_shrinking(script)?;
+impl DatabaseUpdate for DatabaseEntries {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error> {
+        // the details are saved without the raw transaction, which is restored on its own
+        for (txid, details) in &self.txs {
+            match details {
+                Some(details) => database.set_tx(details)?,
+                None => {
+                    database.del_tx(txid, false)?;
+                }
+            }
         }
-        if let Some(rbf) = &self.rbf {
-            match *rbf {
-                RbfValue::Default => {
-                    tx_builder.enable_rbf();
+        for (txid, tx) in &self.raw_txs {
+            match tx {
+                Some(tx) => database.set_raw_tx(tx)?,
+                None => {
+                    database.del_raw_tx(txid)?;
                 }
-                RbfValue::Value(nsequence) => {
-                    tx_builder.enable_rbf_with_sequence(Sequence(nsequence));
+            }
+        }
+        for (outpoint, utxo) in &self.utxos {
+            match utxo {
+                Some(utxo) => database.set_utxo(utxo)?,
+                None => {
+                    database.del_utxo(outpoint)?;
                 }
             }
         }
-        tx_builder
-            .finish()
-            .map(|(psbt, _)| PartiallySignedTransaction {
-                internal: Mutex::new(psbt),
-            })
-            .map(Arc::new)
+        Ok(())
     }
 }
 
-// The goal of these tests to to ensure `bdk-ffi` intermediate code correctly calls `bdk` APIs.
-// These tests should not be used to verify `bdk` behavior that is already tested in the `bdk`
-// crate.
+/// Convert a wallet UTXO, with the confirmation height of the transaction that created it.
+fn local_utxo(wallet: &BdkWallet<AnyDatabase>, utxo: BdkLocalUtxo) -> Result<LocalUtxo, BdkError> {
+    let confirmation_height = wallet
+        .get_tx(&utxo.outpoint.txid, false)?
+        .and_then(|tx_details| tx_details.confirmation_time)
+        .map(|block_time| block_time.height);
+    Ok(LocalUtxo {
+        confirmation_height,
+        ..LocalUtxo::from(utxo)
+    })
+}
+
+/// The indexes of the keychain whose address received a transaction output.
+fn used_indexes(
+    wallet: &BdkWallet<AnyDatabase>,
+    keychain: KeychainKind,
+) -> Result<HashSet<u32>, BdkError> {
+    // wallets without a change descriptor use the external keychain for change addresses
+    let keychain = match wallet.public_descriptor(keychain)? {
+        Some(_) => keychain,
+        None => KeychainKind::External,
+    };
+    let database = wallet.database();
+    let mut used_indexes = HashSet::new();
+    for tx_details in database.iter_txs(true)? {
+        for tx_out in tx_details
+            .transaction
+            .iter()
+            .flat_map(|tx| tx.output.iter())
+        {
+            if let Some((script_keychain, index)) =
+                database.get_path_from_script_pubkey(&tx_out.script_pubkey)?
+            {
+                if script_keychain == keychain {
+                    used_indexes.insert(index);
+                }
+            }
+        }
+    }
+    Ok(used_indexes)
+}
+
+/// Which transactions to return from Wallet.list_transactions_filtered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionFilter {
+    /// All the wallet transactions.
+    All,
+    /// Only the transactions confirmed in a block.
+    Confirmed,
+    /// Only the pending transactions, which aren't confirmed yet.
+    Unconfirmed,
+}
+
+/// The changes to the wallet transactions made by a sync.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    /// Number of transactions the wallet didn't know before the sync.
+    pub new_transactions: u32,
+    /// Number of known transactions whose confirmation changed, e.g. that got confirmed.
+    pub updated_confirmations: u32,
+}
+
+impl SyncReport {
+    fn new(before: &HashMap<Txid, Option<u32>>, after: &HashMap<Txid, Option<u32>>) -> Self {
+        let mut report = SyncReport {
+            new_transactions: 0,
+            updated_confirmations: 0,
+        };
+        for (txid, height) in after {
+            match before.get(txid) {
+                None => report.new_transactions += 1,
+                Some(height_before) if height_before != height => report.updated_confirmations += 1,
+                Some(_) => {}
+            }
+        }
+        report
+    }
+}
+
+/// The version of the format of Wallet.export_backup.
+const BACKUP_VERSION: u64 = 1;
+
+/// Number of blocks after which coinbase outputs can be spent.
+const COINBASE_MATURITY: u32 = 100;
+
+/// An unspent coinbase output which can't be spent yet.
+pub struct ImmatureUtxo {
+    /// The unspent output.
+    pub utxo: LocalUtxo,
+    /// The height from which the output can be spent.
+    pub maturity_height: u32,
+}
+
+/// An input of a wallet transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxInputInfo {
+    /// The output spent by the input.
+    pub outpoint: OutPoint,
+    /// The value of the spent output in satoshis, None if its transaction is unknown.
+    pub amount: Option<u64>,
+    /// The address of the spent output, None if its transaction is unknown or the script has no
+    /// address form.
+    pub address: Option<String>,
+}
+
+/// An output of a wallet transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxOutputInfo {
+    /// The address of the output, None for scripts without an address form.
+    pub address: Option<String>,
+    /// The value of the output, in satoshis.
+    pub amount: u64,
+    /// Whether the output belongs to this wallet.
+    pub is_mine: bool,
+}
+
+/// A key of the wallet descriptors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignerInfo {
+    /// The fingerprint of the master key, in hex.
+    pub fingerprint: String,
+    /// Whether the wallet can sign with this key.
+    pub has_secret: bool,
+}
+
+struct RevealTo {
+    keychain: KeychainKind,
+    index: u32,
+}
+
+impl DatabaseUpdate for RevealTo {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error> {
+        if database
+            .get_last_index(self.keychain)?
+            .map_or(true, |last_index| last_index < self.index)
+        {
+            database.set_last_index(self.keychain, self.index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove everything learned from the blockchain, keeping the cached scripts and the revealed
+/// indices.
+struct ClearChainData;
+
+impl DatabaseUpdate for ClearChainData {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error> {
+        for utxo in database.iter_utxos()? {
+            database.del_utxo(&utxo.outpoint)?;
+        }
+        for tx_details in database.iter_txs(false)? {
+            database.del_tx(&tx_details.txid, true)?;
+        }
+        // raw transactions without details, e.g. the previous transactions of wallet inputs
+        for tx in database.iter_raw_txs()? {
+            database.del_raw_tx(&tx.txid())?;
+        }
+        database.del_sync_time()?;
+        Ok(())
+    }
+}
+
+/// Undo the change address revealed when building a transaction which isn't kept.
+struct RestoreLastIndex {
+    keychain: KeychainKind,
+    last_index: Option<u32>,
+}
+
+impl DatabaseUpdate for RestoreLastIndex {
+    fn apply<D: BatchDatabase>(&self, database: &mut D) -> Result<(), bdk::Error> {
+        match self.last_index {
+            Some(last_index) => database.set_last_index(self.keychain, last_index),
+            None => database.del_last_index(self.keychain).map(|_| ()),
+        }
+    }
+}
+
+/// Ownership information about a PSBT output, as needed by a "verify on device" screen.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PsbtOutputInfo {
+    /// Whether the output's script belongs to this wallet (i.e. it is change or a self-transfer).
+    pub is_mine: bool,
+    /// The derivation path of the output key taken from the PSBT, if present.
+    pub derivation_path: Option<String>,
+}
+
+/// Options for a software signer
+///
+/// Adjust the behavior of our software signers and the way a transaction is finalized
+#[derive(Debug, Clone, Default)]
+pub struct SignOptions {
+    /// Whether the signer should trust the `witness_utxo`, if the `non_witness_utxo` hasn't been
+    /// provided
+    ///
+    /// Defaults to `false` to mitigate the "SegWit bug" which should trick the wallet into
+    /// paying a fee larger than expected.
+    ///
+    /// Some wallets, especially if relatively old, might not provide the `non_witness_utxo` for
+    /// SegWit transactions in the PSBT they generate: in those cases setting this to `true`
+    /// should correctly produce a signature, at the expense of an increased trust in the creator
+    /// of the PSBT.
+    ///
+    /// For more details see: <https://blog.trezor.io/details-of-firmware-updates-for-trezor-one-version-1-9-1-and-trezor-model-t-version-2-3-1-1eba8f60f2dd>
+    pub trust_witness_utxo: bool,
+
+    /// Whether the wallet should assume a specific height has been reached when trying to finalize
+    /// a transaction
+    ///
+    /// The wallet will only "use" a timelock to satisfy the spending policy of an input if the
+    /// timelock height has already been reached. This option allows overriding the "current height" to let the
+    /// wallet use timelocks in the future to spend a coin.
+    pub assume_height: Option<u32>,
+
+    /// Whether the signer should use the `sighash_type` set in the PSBT when signing, no matter
+    /// what its value is
+    ///
+    /// Defaults to `false` which will only allow signing using `SIGHASH_ALL`.
+    pub allow_all_sighashes: bool,
+
+    /// Whether to remove partial signatures from the PSBT inputs while finalizing PSBT.
+    ///
+    /// Defaults to `true` which will remove partial signatures during finalization.
+    pub remove_partial_sigs: bool,
+
+    /// Whether to try finalizing the PSBT after the inputs are signed.
+    ///
+    /// Defaults to `true` which will try finalizing PSBT after inputs are signed.
+    pub try_finalize: bool,
+
+    // Specifies which Taproot script-spend leaves we should sign for. This option is
+    // ignored if we're signing a non-taproot PSBT.
+    //
+    // Defaults to All, i.e., the wallet will sign all the leaves it has a key for.
+    // TODO pub tap_leaves_options: TapLeavesOptions,
+    /// Whether we should try to sign a taproot transaction with the taproot internal key
+    /// or not. This option is ignored if we're signing a non-taproot PSBT.
+    ///
+    /// Defaults to `true`, i.e., we always try to sign with the taproot internal key.
+    pub sign_with_tap_internal_key: bool,
+
+    /// Whether we should grind ECDSA signature to ensure signing with low r
+    /// or not.
+    /// Defaults to `true`, i.e., we always grind ECDSA signature to sign with low r.
+    pub allow_grinding: bool,
+}
+
+impl From<SignOptions> for BdkSignOptions {
+    fn from(sign_options: SignOptions) -> Self {
+        BdkSignOptions {
+            trust_witness_utxo: sign_options.trust_witness_utxo,
+            assume_height: sign_options.assume_height,
+            allow_all_sighashes: sign_options.allow_all_sighashes,
+            remove_partial_sigs: sign_options.remove_partial_sigs,
+            try_finalize: sign_options.try_finalize,
+            tap_leaves_options: Default::default(),
+            sign_with_tap_internal_key: sign_options.sign_with_tap_internal_key,
+            allow_grinding: sign_options.allow_grinding,
+        }
+    }
+}
+
+/// A transaction builder.
+/// After creating the TxBuilder, you set options on it until finally calling finish to consume the builder and generate the transaction.
+/// Each method on the TxBuilder returns an instance of a new TxBuilder with the option set/added.
+#[derive(Clone, Debug)]
+pub(crate) struct TxBuilder {
+    pub(crate) recipients: Vec<(BdkScript, u64)>,
+    pub(crate) utxos: Vec<OutPoint>,
+    pub(crate) unspendable: HashSet<OutPoint>,
+    pub(crate) change_policy: ChangeSpendPolicy,
+    pub(crate) manually_selected_only: bool,
+    pub(crate) spend_from_scripts: Option<Vec<BdkScript>>,
+    pub(crate) fee_rate: Option<f32>,
+    pub(crate) fee_absolute: Option<u64>,
+    pub(crate) drain_wallet: bool,
+    pub(crate) drain_to: Option<BdkScript>,
+    pub(crate) change_to: Option<BdkScript>,
+    pub(crate) no_change_max_extra_fee: Option<u64>,
+    pub(crate) rbf: Option<RbfValue>,
+    pub(crate) nlocktime: Option<u32>,
+    pub(crate) data: Vec<u8>,
+    pub(crate) include_output_redeem_witness_script: bool,
+    pub(crate) only_witness_utxo: bool,
+    pub(crate) add_global_xpubs: bool,
+}
+
+impl TxBuilder {
+    pub(crate) fn new() -> Self {
+        TxBuilder {
+            recipients: Vec::new(),
+            utxos: Vec::new(),
+            unspendable: HashSet::new(),
+            change_policy: ChangeSpendPolicy::ChangeAllowed,
+            manually_selected_only: false,
+            spend_from_scripts: None,
+            fee_rate: None,
+            fee_absolute: None,
+            drain_wallet: false,
+            drain_to: None,
+            change_to: None,
+            no_change_max_extra_fee: None,
+            rbf: None,
+            nlocktime: None,
+            data: Vec::new(),
+            include_output_redeem_witness_script: false,
+            only_witness_utxo: false,
+            add_global_xpubs: false,
+        }
+    }
+
+    /// Add a recipient to the internal list.
+    pub(crate) fn add_recipient(&self, script: Arc<Script>, amount: u64) -> Arc<Self> {
+        let mut recipients: Vec<(BdkScript, u64)> = self.recipients.clone();
+        recipients.append(&mut vec![(script.script.clone(), amount)]);
+        Arc::new(TxBuilder {
+            recipients,
+            ..self.clone()
+        })
+    }
+
+    pub(crate) fn set_recipients(&self, recipients: Vec<ScriptAmount>) -> Arc<Self> {
+        let recipients = recipients
+            .iter()
+            .map(|script_amount| (script_amount.script.script.clone(), script_amount.amount))
+            .collect();
+        Arc::new(TxBuilder {
+            recipients,
+            ..self.clone()
+        })
+    }
+
+    /// Add a utxo to the internal list of unspendable utxos. It’s important to note that the "must-be-spent"
+    /// utxos added with [TxBuilder.addUtxo] have priority over this. See the Rust docs of the two linked methods for more details.
+    pub(crate) fn add_unspendable(&self, unspendable: OutPoint) -> Arc<Self> {
+        let mut unspendable_hash_set = self.unspendable.clone();
+        unspendable_hash_set.insert(unspendable);
+        Arc::new(TxBuilder {
+            unspendable: unspendable_hash_set,
+            ..self.clone()
+        })
+    }
+
+    /// Add an outpoint to the internal list of UTXOs that must be spent. These have priority over the "unspendable"
+    /// utxos, meaning that if a utxo is present both in the "utxos" and the "unspendable" list, it will be spent.
+    pub(crate) fn add_utxo(&self, outpoint: OutPoint) -> Arc<Self> {
+        self.add_utxos(vec![outpoint])
+    }
+
+    /// Add the list of outpoints to the internal list of UTXOs that must be spent. If an error occurs while adding
+    /// any of the UTXOs then none of them are added and the error is returned. These have priority over the "unspendable"
+    /// utxos, meaning that if a utxo is present both in the "utxos" and the "unspendable" list, it will be spent.
+    pub(crate) fn add_utxos(&self, mut outpoints: Vec<OutPoint>) -> Arc<Self> {
+        let mut utxos = self.utxos.to_vec();
+        utxos.append(&mut outpoints);
+        Arc::new(TxBuilder {
+            utxos,
+            ..self.clone()
+        })
+    }
+
+    /// Do not spend change outputs. This effectively adds all the change outputs to the "unspendable" list. See TxBuilder.unspendable.
+    pub(crate) fn do_not_spend_change(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            change_policy: ChangeSpendPolicy::ChangeForbidden,
+            ..self.clone()
+        })
+    }
+
+    /// Only spend utxos added by [add_utxo]. The wallet will not add additional utxos to the transaction even if they are
+    /// needed to make the transaction valid.
+    pub(crate) fn manually_selected_only(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            manually_selected_only: true,
+            ..self.clone()
+        })
+    }
+
+    /// Only spend the wallet's utxos received on the given addresses, for coin control by address.
+    /// The utxos are looked up when calling finish, and are spent like with add_utxos and
+    /// manually_selected_only. Addresses without utxos are ignored, so finish fails with
+    /// BdkError.InsufficientFunds if the addresses don't hold enough funds. Calling this again
+    /// replaces the addresses.
+    pub(crate) fn spend_from_addresses(&self, addresses: Vec<Arc<Address>>) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            spend_from_scripts: Some(
+                addresses
+                    .iter()
+                    .map(|address| address.address.script_pubkey())
+                    .collect(),
+            ),
+            ..self.clone()
+        })
+    }
+
+    /// Only spend change outputs. This effectively adds all the non-change outputs to the "unspendable" list. See TxBuilder.unspendable.
+    pub(crate) fn only_spend_change(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            change_policy: ChangeSpendPolicy::OnlyChange,
+            ..self.clone()
+        })
+    }
+
+    /// Replace the internal list of unspendable utxos with a new list. It’s important to note that the "must-be-spent" utxos added with
+    /// TxBuilder.addUtxo have priority over these. See the Rust docs of the two linked methods for more details.
+    pub(crate) fn unspendable(&self, unspendable: Vec<OutPoint>) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            unspendable: unspendable.into_iter().collect(),
+            ..self.clone()
+        })
+    }
+
+    /// Set a custom fee rate, in sat/vB. Note that the fee rate is a floating point number, which
+    /// can be rounded differently by each language binding. Use fee_rate_sat_per_kwu to set an
+    /// exact fee rate.
+    pub(crate) fn fee_rate(&self, sat_per_vb: f32) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            fee_rate: Some(sat_per_vb),
+            ..self.clone()
+        })
+    }
+
+    /// Set a custom fee rate, in satoshis per 1000 weight units (sat/kwu). The integer rate is
+    /// converted to sat/vB on the Rust side, so it produces the same fee in every language binding.
+    pub(crate) fn fee_rate_sat_per_kwu(&self, sat_per_kwu: u64) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            fee_rate: Some(FeeRate::from_wu(sat_per_kwu, 1000).as_sat_per_vb()),
+            ..self.clone()
+        })
+    }
+
+    /// Set an absolute fee.
+    pub(crate) fn fee_absolute(&self, fee_amount: u64) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            fee_absolute: Some(fee_amount),
+            ..self.clone()
+        })
+    }
+
+    /// Spend all the available inputs. This respects filters like TxBuilder.unspendable and the change policy.
+    pub(crate) fn drain_wallet(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            drain_wallet: true,
+            ..self.clone()
+        })
+    }
+
+    /// Sets the address to drain excess coins to. Usually, when there are excess coins they are sent to a change address
+    /// generated by the wallet. This option replaces the usual change address with an arbitrary ScriptPubKey of your choosing.
+    /// Just as with a change output, if the drain output is not needed (the excess coins are too small) it will not be included
+    /// in the resulting transaction. The only difference is that it is valid to use drain_to without setting any ordinary recipients
+    /// with add_recipient (but it is perfectly fine to add recipients as well). If you choose not to set any recipients, you should
+    /// either provide the utxos that the transaction should spend via add_utxos, or set drain_wallet to spend all of them.
+    /// When bumping the fees of a transaction made with this option, you probably want to use BumpFeeTxBuilder.allow_shrinking
+    /// to allow this output to be reduced to pay for the extra fees.
+    pub(crate) fn drain_to(&self, script: Arc<Script>) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            drain_to: Some(script.script.clone()),
+            ..self.clone()
+        })
+    }
+
+    /// Send the change to the given script instead of an address of the wallet's internal keychain,
+    /// e.g. a cold-storage descriptor. Unlike drain_to this can't be combined with drain_wallet,
+    /// and TxBuilderResult.change_output_index still points to the change output. Note that the
+    /// change then isn't tracked by this wallet, and that paying change to a script type or
+    /// address that was seen before makes it easier to tell the change apart from the payment.
+    pub(crate) fn change_to(&self, script: Arc<Script>) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            change_to: Some(script.script.clone()),
+            ..self.clone()
+        })
+    }
+
+    /// Never add a change output, the excess of the coin selection is added to the fee instead.
+    /// finish fails if that extra fee is more than max_extra_fee sats, so pick the utxos or
+    /// amounts to spend close to the total. This can't be combined with drain_wallet, drain_to or
+    /// change_to.
+    pub(crate) fn no_change(&self, max_extra_fee: u64) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            no_change_max_extra_fee: Some(max_extra_fee),
+            ..self.clone()
+        })
+    }
+
+    /// Enable signaling RBF. This will use the default `nsequence` value of `0xFFFFFFFD`.
+    pub(crate) fn enable_rbf(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            rbf: Some(RbfValue::Default),
+            ..self.clone()
+        })
+    }
+
+    /// Enable signaling RBF with a specific nSequence value. This can cause conflicts if the wallet's descriptors contain an
+    /// "older" (OP_CSV) operator and the given `nsequence` is lower than the CSV value. If the `nsequence` is higher than `0xFFFFFFFD`
+    /// an error will be thrown, since it would not be a valid nSequence to signal RBF.
+    pub(crate) fn enable_rbf_with_sequence(&self, nsequence: u32) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            rbf: Some(RbfValue::Value(nsequence)),
+            ..self.clone()
+        })
+    }
+
+    /// Use a specific nLockTime, a block height below 500,000,000 or a unix timestamp otherwise,
+    /// so the transaction can't be mined before it. By default BDK uses the current height to
+    /// discourage fee sniping. The value set is available as Transaction.lock_time of the result.
+    pub(crate) fn nlocktime(&self, nlocktime: u32) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            nlocktime: Some(nlocktime),
+            ..self.clone()
+        })
+    }
+
+    /// Add data as an output using OP_RETURN.
+    pub(crate) fn add_data(&self, data: Vec<u8>) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            data,
+            ..self.clone()
+        })
+    }
+
+    /// Fill-in the PSBT_GLOBAL_XPUB field with the extended keys contained in both the external
+    /// and internal descriptors. This is useful for offline signers that take part to a multisig
+    /// or need to verify change outputs. Some hardware wallets refuse to sign PSBTs without it.
+    pub(crate) fn add_global_xpubs(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            add_global_xpubs: true,
+            ..self.clone()
+        })
+    }
+
+    /// Only fill-in the PSBT_IN_WITNESS_UTXO field when spending from SegWit descriptors. This
+    /// reduces the size of the PSBT, but some signers require the full previous transaction
+    /// (PSBT_IN_NON_WITNESS_UTXO) even for SegWit inputs.
+    pub(crate) fn only_witness_utxo(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            only_witness_utxo: true,
+            ..self.clone()
+        })
+    }
+
+    /// Fill-in the PSBT_OUT_REDEEM_SCRIPT and PSBT_OUT_WITNESS_SCRIPT fields of the wallet's own
+    /// outputs (e.g. change), as required by some signers to recognize them.
+    pub(crate) fn include_output_redeem_witness_script(&self) -> Arc<Self> {
+        Arc::new(TxBuilder {
+            include_output_redeem_witness_script: true,
+            ..self.clone()
+        })
+    }
+
+    /// Finish building the transaction. Returns the BIP174 PSBT.
+    pub(crate) fn finish(&self, wallet: &Wallet) -> Result<TxBuilderResult, BdkError> {
+        self.check_recipients_total()?;
+        let wallet = wallet.get_wallet();
+        let (mut psbt, mut tx_details) = self.build(&wallet)?;
+
+        // BDK doesn't report which output is the change, it is the wallet's own output that isn't
+        // one of the recipients. An output added with drain_to replaces the change output.
+        let mut change_output_index = None;
+        if self.drain_to.is_none() {
+            for (index, tx_out) in psbt.unsigned_tx.output.iter().enumerate() {
+                let is_recipient = self
+                    .recipients
+                    .iter()
+                    .any(|(script, _)| *script == tx_out.script_pubkey);
+                let is_change = match &self.change_to {
+                    Some(script) => *script == tx_out.script_pubkey,
+                    None => wallet.is_mine(&tx_out.script_pubkey)?,
+                };
+                if !is_recipient && is_change {
+                    change_output_index = Some(index as u32);
+                    break;
+                }
+            }
+        }
+
+        // BDK always adds a change output when the excess is above the dust limit, so it is
+        // removed here and its value goes to the fee
+        if let (Some(max_extra_fee), Some(index)) =
+            (self.no_change_max_extra_fee, change_output_index)
+        {
+            change_output_index = None;
+            let index = index as usize;
+            let extra_fee = psbt.unsigned_tx.output[index].value;
+            if extra_fee > max_extra_fee {
+                return Err(BdkError::Generic { error_message: format!(
+                    "The transaction would need a change output of {} sat, more than the maximum extra fee of {} sat",
+                    extra_fee, max_extra_fee
+                ) });
+            }
+            psbt.unsigned_tx.output.remove(index);
+            psbt.outputs.remove(index);
+            tx_details.txid = psbt.unsigned_tx.txid();
+            tx_details.received = tx_details.received.saturating_sub(extra_fee);
+            tx_details.fee = tx_details.fee.map(|fee| fee + extra_fee);
+            tx_details.transaction = Some(psbt.unsigned_tx.clone());
+        }
+
+        Ok(TxBuilderResult {
+            psbt: Arc::new(PartiallySignedTransaction {
+                internal: Mutex::new(psbt),
+            }),
+            transaction_details: TransactionDetails::from(tx_details),
+            change_output_index,
+        })
+    }
+
+    /// Run the coin selection and return the fee the transaction would pay, without keeping the
+    /// transaction, e.g. to show the projected fee while the user picks a fee rate. Unlike finish,
+    /// this doesn't reveal a new change address.
+    pub(crate) fn estimate_fee(&self, wallet: &Wallet) -> Result<u64, BdkError> {
+        let wallet = wallet.get_wallet();
+        // wallets without a change descriptor use the external keychain for change addresses
+        let keychain = match wallet.public_descriptor(KeychainKind::Internal)? {
+            Some(_) => KeychainKind::Internal,
+            None => KeychainKind::External,
+        };
+        let last_index = wallet.database().get_last_index(keychain)?;
+        let result = self.build(&wallet);
+        apply_database_update(
+            &wallet,
+            RestoreLastIndex {
+                keychain,
+                last_index,
+            },
+        )?;
+        let (_, tx_details) = result?;
+        tx_details.fee.ok_or_else(|| BdkError::Generic {
+            error_message: "The fee of the transaction is unknown".to_string(),
+        })
+    }
+
+    /// The recipient amounts come from the bindings, so their total is checked before it can
+    /// overflow in the coin selection.
+    fn check_recipients_total(&self) -> Result<(), BdkError> {
+        let total = self
+            .recipients
+            .iter()
+            .try_fold(0u64, |total, (_, amount)| total.checked_add(*amount))
+            .ok_or_else(|| BdkError::Generic {
+                error_message: "The total amount of the recipients overflows".to_string(),
+            })?;
+        if total > Amount::MAX_MONEY.to_sat() {
+            return Err(BdkError::Generic {
+                error_message: format!(
+                    "The total amount of the recipients ({} sat) exceeds the maximum money supply",
+                    total
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    fn build(
+        &self,
+        wallet: &BdkWallet<AnyDatabase>,
+    ) -> Result<(BdkPartiallySignedTransaction, BdkTransactionDetails), BdkError> {
+        let mut tx_builder = wallet.build_tx();
+        for (script, amount) in &self.recipients {
+            tx_builder.add_recipient(script.clone(), *amount);
+        }
+        tx_builder.change_policy(self.change_policy);
+        if !self.utxos.is_empty() {
+            let bdk_utxos: Vec<BdkOutPoint> = self.utxos.iter().map(BdkOutPoint::from).collect();
+            let utxos: &[BdkOutPoint] = &bdk_utxos;
+            tx_builder.add_utxos(utxos)?;
+        }
+        if !self.unspendable.is_empty() {
+            let bdk_unspendable: Vec<BdkOutPoint> =
+                self.unspendable.iter().map(BdkOutPoint::from).collect();
+            tx_builder.unspendable(bdk_unspendable);
+        }
+        if self.manually_selected_only {
+            tx_builder.manually_selected_only();
+        }
+        if let Some(scripts) = &self.spend_from_scripts {
+            let utxos: Vec<BdkOutPoint> = wallet
+                .list_unspent()?
+                .into_iter()
+                .filter(|utxo| scripts.contains(&utxo.txout.script_pubkey))
+                .map(|utxo| utxo.outpoint)
+                .collect();
+            tx_builder.add_utxos(&utxos)?;
+            tx_builder.manually_selected_only();
+        }
+        if let Some(sat_per_vb) = self.fee_rate {
+            tx_builder.fee_rate(FeeRate::from_sat_per_vb(sat_per_vb));
+        }
+        if let Some(fee_amount) = self.fee_absolute {
+            tx_builder.fee_absolute(fee_amount);
+        }
+        if self.drain_wallet {
+            tx_builder.drain_wallet();
+        }
+        if let Some(script) = &self.drain_to {
+            tx_builder.drain_to(script.clone());
+        }
+        if self.no_change_max_extra_fee.is_some()
+            && (self.drain_wallet || self.drain_to.is_some() || self.change_to.is_some())
+        {
+            return Err(BdkError::Generic {
+                error_message:
+                    "no_change can't be used together with drain_wallet, drain_to or change_to"
+                        .to_string(),
+            });
+        }
+        // BDK uses the drain script for the change when the wallet isn't drained
+        if let Some(script) = &self.change_to {
+            if self.drain_wallet || self.drain_to.is_some() {
+                return Err(BdkError::Generic {
+                    error_message: "change_to can't be used together with drain_wallet or drain_to"
+                        .to_string(),
+                });
+            }
+            tx_builder.drain_to(script.clone());
+        }
+        if let Some(rbf) = &self.rbf {
+            match *rbf {
+                RbfValue::Default => {
+                    tx_builder.enable_rbf();
+                }
+                RbfValue::Value(nsequence) => {
+                    tx_builder.enable_rbf_with_sequence(Sequence(nsequence));
+                }
+            }
+        }
+        if let Some(nlocktime) = self.nlocktime {
+            tx_builder.nlocktime(LockTime::from_consensus(nlocktime));
+        }
+        if !&self.data.is_empty() {
+            tx_builder.add_data(self.data.as_slice());
+        }
+
+        if self.add_global_xpubs {
+            tx_builder.add_global_xpubs();
+        }
+
+        if self.only_witness_utxo {
+            tx_builder.only_witness_utxo();
+        }
+
+        if self.include_output_redeem_witness_script {
+            tx_builder.include_output_redeem_witness_script();
+        }
+
+        Ok(tx_builder.finish()?)
+    }
+}
+
+/// The BumpFeeTxBuilder is used to bump the fee on a transaction that has been broadcast and has its RBF flag set to true.
+#[derive(Clone)]
+pub(crate) struct BumpFeeTxBuilder {
+    pub(crate) txid: String,
+    pub(crate) fee_rate: f32,
+    pub(crate) allow_shrinking: Option<String>,
+    pub(crate) rbf: Option<RbfValue>,
+}
+
+impl BumpFeeTxBuilder {
+    pub(crate) fn new(txid: String, fee_rate: f32) -> Self {
+        Self {
+            txid,
+            fee_rate,
+            allow_shrinking: None,
+            rbf: None,
+        }
+    }
+
+    /// Explicitly tells the wallet that it is allowed to reduce the amount of the output matching this script_pubkey
+    /// in order to bump the transaction fee. Without specifying this the wallet will attempt to find a change output to
+    /// shrink instead. Note that the output may shrink to below the dust limit and therefore be removed. If it is preserved
+    /// then it is currently not guaranteed to be in the same position as it was originally. Returns an error if script_pubkey
+    /// can’t be found among the recipients of the transaction we are bumping.
+    pub(crate) fn allow_shrinking(&self, address: String) -> Arc<Self> {
+        Arc::new(Self {
+            allow_shrinking: Some(address),
+            ..self.clone()
+        })
+    }
+
+    /// Enable signaling RBF. This will use the default `nsequence` value of `0xFFFFFFFD`.
+    pub(crate) fn enable_rbf(&self) -> Arc<Self> {
+        Arc::new(Self {
+            rbf: Some(RbfValue::Default),
+            ..self.clone()
+        })
+    }
+
+    /// Enable signaling RBF with a specific nSequence value. This can cause conflicts if the wallet's descriptors contain an
+    /// "older" (OP_CSV) operator and the given `nsequence` is lower than the CSV value. If the `nsequence` is higher than `0xFFFFFFFD`
+    /// an error will be thrown, since it would not be a valid nSequence to signal RBF.
+    pub(crate) fn enable_rbf_with_sequence(&self, nsequence: u32) -> Arc<Self> {
+        Arc::new(Self {
+            rbf: Some(RbfValue::Value(nsequence)),
+            ..self.clone()
+        })
+    }
+
+    /// Finish building the transaction. Returns the BIP174 PSBT.
+    ///
+    /// The transaction to bump must be an unconfirmed wallet transaction signaling RBF, otherwise
+    /// a specific error is returned: BdkError.TransactionNotFound if the wallet doesn't know the
+    /// transaction, BdkError.TransactionConfirmed if it is already confirmed and
+    /// BdkError.IrreplaceableTransaction if it doesn't signal RBF.
+    pub(crate) fn finish(
+        &self,
+        wallet: &Wallet,
+    ) -> Result<Arc<PartiallySignedTransaction>, BdkError> {
+        let wallet = wallet.get_wallet();
+        let txid = Txid::from_str(self.txid.as_str())?;
+        let mut tx_builder = wallet.build_fee_bump(txid)?;
+        tx_builder.fee_rate(FeeRate::from_sat_per_vb(self.fee_rate));
+        if let Some(allow_shrinking) = &self.allow_shrinking {
+            let address = BdkAddress::from_str(allow_shrinking).map_err(|e| BdkError::Generic {
+                error_message: e.to_string(),
+            })?;
+            let script = address.script_pubkey();
+            tx_builder.allow_shrinking(script)?;
+        }
+        if let Some(rbf) = &self.rbf {
+            match *rbf {
+                RbfValue::Default => {
+                    tx_builder.enable_rbf();
+                }
+                RbfValue::Value(nsequence) => {
+                    tx_builder.enable_rbf_with_sequence(Sequence(nsequence));
+                }
+            }
+        }
+        let (psbt, _) = tx_builder.finish()?;
+        Ok(Arc::new(PartiallySignedTransaction {
+            internal: Mutex::new(psbt),
+        }))
+    }
+
+    /// Like finish, for a transaction the wallet doesn't know yet, e.g. a collaborative
+    /// transaction like a payjoin received as raw hex. The PSBT of the transaction must give the
+    /// full previous transaction (non_witness_utxo) of every input, which are needed to compute
+    /// the fee. The previous transactions are saved in the wallet database and the transaction is
+    /// inserted like with Wallet.insert_tx, then it is bumped like a wallet transaction. If the
+    /// bump fails, the database is left as it was. The txid this builder was created with must be
+    /// the one of the transaction.
+    pub(crate) fn finish_for_transaction(
+        &self,
+        wallet: &Wallet,
+        transaction: Arc<Transaction>,
+        psbt: Arc<PartiallySignedTransaction>,
+    ) -> Result<Arc<PartiallySignedTransaction>, BdkError> {
+        let tx = &transaction.internal;
+        if tx.txid().to_string() != self.txid {
+            return Err(BdkError::Generic {
+                error_message: format!(
+                    "The transaction {} isn't the one to bump, {}",
+                    tx.txid(),
+                    self.txid
+                ),
+            });
+        }
+        let psbt = psbt.internal.lock().unwrap().clone();
+        let same_inputs = psbt.unsigned_tx.input.len() == tx.input.len()
+            && psbt
+                .unsigned_tx
+                .input
+                .iter()
+                .zip(&tx.input)
+                .all(|(psbt_input, input)| psbt_input.previous_output == input.previous_output);
+        if !same_inputs {
+            return Err(BdkError::Generic {
+                error_message: "The PSBT doesn't spend the inputs of the transaction".to_string(),
+            });
+        }
+        let previous_txs = psbt
+            .inputs
+            .iter()
+            .zip(&tx.input)
+            .map(|(psbt_input, input)| {
+                psbt_input
+                    .non_witness_utxo
+                    .clone()
+                    .ok_or_else(|| BdkError::Generic {
+                        error_message: format!(
+                            "The previous transaction of input {} is missing",
+                            input.previous_output
+                        ),
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // bdk bumps transactions from the database, so they are inserted before the bump and
+        // removed again if it fails
+        let previous_entries = {
+            let bdk_wallet = wallet.get_wallet();
+            let database = bdk_wallet.database();
+            DatabaseEntries::new(database.deref(), tx, &previous_txs)?
+        };
+        let result = self.insert_and_finish(wallet, transaction, previous_txs);
+        if result.is_err() {
+            wallet.update_database(previous_entries)?;
+        }
+        result
+    }
+
+    fn insert_and_finish(
+        &self,
+        wallet: &Wallet,
+        transaction: Arc<Transaction>,
+        previous_txs: Vec<BdkTransaction>,
+    ) -> Result<Arc<PartiallySignedTransaction>, BdkError> {
+        wallet.update_database(InsertRawTxs { txs: previous_txs })?;
+        let is_known = wallet
+            .get_wallet()
+            .database()
+            .get_tx(&transaction.internal.txid(), false)?
+            .is_some();
+        if !is_known {
+            wallet.insert_tx(transaction, None)?;
+        }
+        self.finish(wallet)
+    }
+}
+
+// The goal of these tests to to ensure `bdk-ffi` intermediate code correctly calls `bdk` APIs.
+// These tests should not be used to verify `bdk` behavior that is already tested in the `bdk`
+// crate.
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
+    use crate::blockchain::test::offline_esplora;
     use crate::database::DatabaseConfig;
     use crate::descriptor::Descriptor;
-    use crate::wallet::{AddressIndex, TxBuilder, Wallet};
-    use bdk::bitcoin::{Address, Network};
+    use crate::psbt::PartiallySignedTransaction;
+    use crate::wallet::{
+        AddressIndex, BumpFeeTxBuilder, SignOptions, SignerInfo, SyncReport, TransactionFilter,
+        TxBuilder, Wallet,
+    };
+    use crate::{BdkError, ScriptAmount, ScriptType};
+    use assert_matches::assert_matches;
+    use bdk::bitcoin::secp256k1::{Message, Secp256k1};
+    use bdk::bitcoin::util::bip32::{DerivationPath, ExtendedPrivKey};
+    use bdk::bitcoin::{
+        Address, Network, OutPoint, PackedLockTime, Transaction, TxIn, TxOut, Txid,
+    };
+    use bdk::blockchain::{GetHeight, Progress as BdkProgress, WalletSync};
+    use bdk::database::any::SledDbConfiguration;
+    use bdk::database::{BatchDatabase, Database};
     use bdk::wallet::get_funded_wallet;
+    use bdk::{BlockTime, KeychainKind, SyncOptions as BdkSyncOptions};
+    use std::cell::RefCell;
     use std::str::FromStr;
     use std::sync::{Arc, Mutex};
 
+    /// A wallet with a confirmed 50_000 sat utxo, see get_funded_wallet, and the txid of the
+    /// transaction funding it.
+    pub(crate) fn funded_test_wallet(descriptor: &str) -> (Wallet, Txid) {
+        let (funded_wallet, _, funding_txid) = get_funded_wallet(descriptor);
+        let wallet = Wallet {
+            wallet_mutex: Mutex::new(funded_wallet),
+        };
+        (wallet, funding_txid)
+    }
+
+    /// An address which doesn't belong to the test wallets.
+    pub(crate) fn test_address() -> crate::Address {
+        crate::Address::new("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt".to_string()).unwrap()
+    }
+
+    /// The script_pubkey of test_address, to send to.
+    pub(crate) fn test_recipient() -> Arc<crate::Script> {
+        test_address().script_pubkey()
+    }
+
+    /// An outpoint of a transaction unknown to the test wallets.
+    fn unknown_outpoint() -> OutPoint {
+        OutPoint::new(
+            Txid::from_str("312f1733badab22dc26b8dcbc83ba5629fb7b493af802e8abe07d865e49629c5")
+                .unwrap(),
+            0,
+        )
+    }
+
+    /// A transaction from outside the wallet paying `value` to `script_pubkey`.
+    fn receive_tx(script_pubkey: bdk::bitcoin::Script, value: u64) -> Transaction {
+        Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: unknown_outpoint(),
+                ..Default::default()
+            }],
+            output: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+        }
+    }
+
+    /// An offline blockchain without any transactions, to record a sync of a wallet at `height`.
+    struct TestChain {
+        height: u32,
+    }
+
+    impl WalletSync for TestChain {
+        fn wallet_setup<D: BatchDatabase>(
+            &self,
+            _database: &RefCell<D>,
+            _progress_update: Box<dyn BdkProgress>,
+        ) -> Result<(), bdk::Error> {
+            Ok(())
+        }
+    }
+
+    impl GetHeight for TestChain {
+        fn get_height(&self) -> Result<u32, bdk::Error> {
+            Ok(self.height)
+        }
+    }
+
+    #[test]
+    fn test_drain_wallet() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (funded_wallet, _, _) = get_funded_wallet(test_wpkh);
+        let test_wallet = Wallet {
+            wallet_mutex: Mutex::new(funded_wallet),
+        };
+        let drain_to_address = "tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt".to_string();
+        let drain_to_script = crate::Address::new(drain_to_address)
+            .unwrap()
+            .script_pubkey();
+        let tx_builder = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script.clone());
+        assert!(tx_builder.drain_wallet);
+        assert_eq!(tx_builder.drain_to, Some(drain_to_script.script.clone()));
+
+        let tx_builder_result = tx_builder.finish(&test_wallet).unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        let tx_details = tx_builder_result.transaction_details;
+
+        // confirm one input with 50,000 sats
+        assert_eq!(psbt.inputs.len(), 1);
+        let input_value = psbt
+            .inputs
+            .get(0)
+            .cloned()
+            .unwrap()
+            .non_witness_utxo
+            .unwrap()
+            .output
+            .get(0)
+            .unwrap()
+            .value;
+        assert_eq!(input_value, 50_000_u64);
+
+        // confirm one output to correct address with all sats - fee
+        assert_eq!(psbt.outputs.len(), 1);
+        let output_address = Address::from_script(
+            &psbt
+                .unsigned_tx
+                .output
+                .get(0)
+                .cloned()
+                .unwrap()
+                .script_pubkey,
+            Network::Testnet,
+        )
+        .unwrap();
+        assert_eq!(
+            output_address,
+            Address::from_str("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt").unwrap()
+        );
+        let output_value = psbt.unsigned_tx.output.get(0).cloned().unwrap().value;
+        assert_eq!(output_value, 49_890_u64); // input - fee
+
+        assert_eq!(
+            tx_details.txid,
+            "312f1733badab22dc26b8dcbc83ba5629fb7b493af802e8abe07d865e49629c5"
+        );
+        assert_eq!(tx_details.received, 0);
+        assert_eq!(tx_details.sent, 50000);
+        // the fee is part of sent
+        assert_eq!(tx_details.net, -50000);
+        assert!(tx_details.fee.is_some());
+        assert_eq!(tx_details.fee.unwrap(), 110);
+        assert!(tx_details.confirmation_time.is_none());
+    }
+
+    #[test]
+    fn test_change_output_index() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient.clone(), 25_000)
+            .finish(&test_wallet)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+        let change_output_index = tx_builder_result.change_output_index.unwrap() as usize;
+        let change_output = psbt.unsigned_tx.output.get(change_output_index).unwrap();
+        assert_ne!(change_output.script_pubkey, recipient.script);
+        assert!(test_wallet
+            .get_wallet()
+            .is_mine(&change_output.script_pubkey)
+            .unwrap());
+
+        // there is no change output when draining
+        let tx_builder_result = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(recipient)
+            .finish(&test_wallet)
+            .unwrap();
+        assert!(tx_builder_result.change_output_index.is_none());
+    }
+
+    #[test]
+    fn test_send_to_derived_address() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+
+        // the derived address is an Address object, it doesn't need to be parsed again
+        let address_info = test_wallet.get_address(AddressIndex::New).unwrap();
+        let recipient = address_info.address.script_pubkey();
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient.clone(), 25_000)
+            .finish(&test_wallet)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        assert!(psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .any(|tx_out| tx_out.script_pubkey == recipient.script && tx_out.value == 25_000));
+    }
+
+    #[test]
+    fn test_change_to() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+        let change_script = crate::Address::new("mkHS9ne12qx9pS9VojpwU5xtRd4T7X7ZUt".to_string())
+            .unwrap()
+            .script_pubkey();
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient.clone(), 25_000)
+            .change_to(change_script.clone())
+            .finish(&test_wallet)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        assert_eq!(psbt.unsigned_tx.output.len(), 2);
+        let change_output_index = tx_builder_result.change_output_index.unwrap() as usize;
+        let change_output = psbt.unsigned_tx.output.get(change_output_index).unwrap();
+        assert_eq!(change_output.script_pubkey, change_script.script);
+        assert!(!test_wallet
+            .get_wallet()
+            .is_mine(&change_output.script_pubkey)
+            .unwrap());
+
+        let error = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(recipient)
+            .change_to(change_script)
+            .finish(&test_wallet)
+            .unwrap_err();
+        assert_matches!(error, BdkError::Generic { .. });
+    }
+
+    #[test]
+    fn test_no_change() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        // the 50_000 sat utxo leaves almost 25_000 sat of change
+        let error = TxBuilder::new()
+            .add_recipient(recipient.clone(), 25_000)
+            .no_change(1_000)
+            .finish(&test_wallet)
+            .unwrap_err();
+        assert_matches!(error, BdkError::Generic { .. });
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient.clone(), 49_000)
+            .no_change(1_000)
+            .finish(&test_wallet)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        assert_eq!(psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(psbt.outputs.len(), 1);
+        assert!(tx_builder_result.change_output_index.is_none());
+        let transaction_details = tx_builder_result.transaction_details;
+        assert_eq!(transaction_details.fee, Some(1_000));
+        assert_eq!(transaction_details.received, 0);
+        assert_eq!(
+            transaction_details.txid,
+            psbt.unsigned_tx.txid().to_string()
+        );
+
+        let error = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(recipient)
+            .no_change(1_000)
+            .finish(&test_wallet)
+            .unwrap_err();
+        assert_matches!(error, BdkError::Generic { .. });
+    }
+
+    #[test]
+    fn test_policies() {
+        let test_multisig = "wsh(multi(2,0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798,02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5,02f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9))";
+        let wallet = Wallet::new(
+            Arc::new(Descriptor::new(test_multisig.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+            DatabaseConfig::Memory,
+        )
+        .unwrap();
+
+        let policy = wallet.policies(KeychainKind::External).unwrap().unwrap();
+        assert!(policy.contains("\"type\":\"MULTISIG\""));
+        assert!(policy.contains("\"threshold\":2"));
+    }
+
+    #[test]
+    fn test_list_unspent_for_keychain() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+
+        let external = test_wallet
+            .list_unspent_for_keychain(KeychainKind::External)
+            .unwrap();
+        assert_eq!(external.len(), 1);
+        assert_eq!(external[0].keychain, KeychainKind::External);
+        assert!(test_wallet
+            .list_unspent_for_keychain(KeychainKind::Internal)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_utxo_confirmation_height() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, funding_txid) = funded_test_wallet(test_wpkh);
+        let unspent = test_wallet.list_unspent().unwrap();
+        assert_eq!(unspent.len(), 1);
+        assert!(unspent[0].confirmation_height.is_some());
+
+        // the change of an unconfirmed transaction is unconfirmed too
+        let recipient = test_recipient();
+        let tx = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt
+            .extract_tx();
+        test_wallet.insert_tx(tx, None).unwrap();
+        let unspent = test_wallet.list_unspent().unwrap();
+        assert_eq!(unspent.len(), 1);
+        assert_ne!(unspent[0].outpoint.txid, funding_txid.to_string());
+        assert_eq!(unspent[0].confirmation_height, None);
+    }
+
+    #[test]
+    fn test_get_utxo() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let unspent = test_wallet.list_unspent().unwrap();
+        let outpoint = unspent.get(0).unwrap().outpoint.clone();
+
+        let utxo = test_wallet.get_utxo(outpoint.clone()).unwrap().unwrap();
+        assert_eq!(utxo.outpoint, outpoint);
+        assert_eq!(utxo.txout.value, 50_000);
+        assert!(!utxo.is_spent);
+
+        let unknown_outpoint = crate::OutPoint {
+            txid: outpoint.txid.clone(),
+            vout: outpoint.vout + 1,
+        };
+        assert!(test_wallet.get_utxo(unknown_outpoint).unwrap().is_none());
+
+        let invalid_outpoint = crate::OutPoint {
+            txid: "not a txid".to_string(),
+            vout: 0,
+        };
+        assert!(test_wallet.get_utxo(invalid_outpoint).is_err());
+    }
+
+    #[test]
+    fn test_add_global_xpubs() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient.clone(), 25_000)
+            .finish(&test_wallet)
+            .unwrap();
+        assert!(tx_builder_result
+            .psbt
+            .internal
+            .lock()
+            .unwrap()
+            .xpub
+            .is_empty());
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .add_global_xpubs()
+            .finish(&test_wallet)
+            .unwrap();
+        assert_eq!(
+            tx_builder_result.psbt.internal.lock().unwrap().xpub.len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_only_witness_utxo() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient.clone(), 25_000)
+            .finish(&test_wallet)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        assert!(psbt.inputs[0].witness_utxo.is_some());
+        assert!(psbt.inputs[0].non_witness_utxo.is_some());
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .only_witness_utxo()
+            .include_output_redeem_witness_script()
+            .finish(&test_wallet)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+        assert!(psbt.inputs[0].witness_utxo.is_some());
+        assert!(psbt.inputs[0].non_witness_utxo.is_none());
+    }
+
+    #[test]
+    fn test_fee_rate_sat_per_kwu() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+
+        // 500 sat/kwu is 2 sat/vB
+        let tx_builder_result = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .fee_rate_sat_per_kwu(500)
+            .finish(&test_wallet)
+            .unwrap();
+        assert_eq!(tx_builder_result.transaction_details.fee, Some(220));
+    }
+
     #[test]
-    fn test_drain_wallet() {
+    fn test_estimate_fee() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let last_index = || {
+            test_wallet
+                .get_wallet()
+                .database()
+                .get_last_index(KeychainKind::External)
+                .unwrap()
+        };
+        let recipient_script = test_recipient();
+        let tx_builder = TxBuilder::new()
+            .add_recipient(recipient_script, 10_000)
+            .fee_rate(5.0);
+
+        let index_before = last_index();
+        let estimated_fee = tx_builder.estimate_fee(&test_wallet).unwrap();
+        // the change address revealed while estimating is given back
+        assert_eq!(last_index(), index_before);
+
+        let tx_builder_result = tx_builder.finish(&test_wallet).unwrap();
+        assert!(tx_builder_result.change_output_index.is_some());
+        assert_eq!(
+            tx_builder_result.transaction_details.fee,
+            Some(estimated_fee)
+        );
+        assert_ne!(last_index(), index_before);
+
+        let too_much = TxBuilder::new().add_recipient(test_recipient(), 100_000);
+        assert_matches!(
+            too_much.estimate_fee(&test_wallet),
+            Err(BdkError::InsufficientFunds { .. })
+        );
+    }
+
+    #[test]
+    fn test_spend_from_addresses() {
         let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
-        let (funded_wallet, _, _) = get_funded_wallet(test_wpkh);
-        let test_wallet = Wallet {
-            wallet_mutex: Mutex::new(funded_wallet),
+        let (test_wallet, funding_txid) = funded_test_wallet(test_wpkh);
+        let wallet_address = test_wallet
+            .get_address(AddressIndex::LastUnused)
+            .unwrap()
+            .address;
+        let other_address = Arc::new(test_address());
+
+        let tx_builder = TxBuilder::new().add_recipient(other_address.script_pubkey(), 10_000);
+        let psbt = tx_builder
+            .spend_from_addresses(vec![wallet_address])
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+        let inputs = psbt.internal.lock().unwrap().unsigned_tx.input.clone();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].previous_output, OutPoint::new(funding_txid, 0));
+
+        // the wallet has no utxos on the other address
+        assert_matches!(
+            tx_builder
+                .spend_from_addresses(vec![other_address])
+                .finish(&test_wallet),
+            Err(BdkError::InsufficientFunds { .. })
+        );
+    }
+
+    #[test]
+    fn test_nlocktime() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+
+        let tx_builder = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .nlocktime(630_000);
+        assert_eq!(tx_builder.nlocktime, Some(630_000));
+
+        let tx = tx_builder.finish(&test_wallet).unwrap().psbt.extract_tx();
+        assert_eq!(tx.lock_time(), 630_000);
+        assert!(tx.is_lock_time_enabled());
+    }
+
+    #[test]
+    fn test_insufficient_funds() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        // the bindings get the amounts, the amount needed includes the fee
+        let error = TxBuilder::new()
+            .add_recipient(recipient, 60_000)
+            .finish(&test_wallet)
+            .unwrap_err();
+        assert_matches!(error, BdkError::InsufficientFunds { needed, available } => {
+            assert!(needed > 60_000 && needed < 61_000);
+            assert_eq!(available, 50_000);
+        });
+        assert!(error.to_string().contains("50000 sat available"));
+    }
+
+    #[test]
+    fn test_recipients_total_overflow() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        let error = TxBuilder::new()
+            .add_recipient(recipient.clone(), u64::MAX)
+            .add_recipient(recipient.clone(), 1)
+            .finish(&test_wallet)
+            .unwrap_err();
+        assert_matches!(error, BdkError::Generic { error_message: message } => {
+            assert!(message.contains("overflows"));
+        });
+
+        let error = TxBuilder::new()
+            .add_recipient(recipient.clone(), 20_000_000 * 100_000_000)
+            .add_recipient(recipient, 1_000_000 * 100_000_000 + 1)
+            .finish(&test_wallet)
+            .unwrap_err();
+        assert_matches!(error, BdkError::Generic { error_message: message } => {
+            assert!(message.contains("maximum money supply"));
+        });
+    }
+
+    #[test]
+    fn test_get_tx_inputs() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, funding_txid) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let tx = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt
+            .extract_tx();
+        let txid = tx.txid();
+        test_wallet.insert_tx(tx, None).unwrap();
+
+        // the spent output belongs to the funding transaction known to the wallet
+        let inputs = test_wallet.get_tx_inputs(txid).unwrap();
+        assert_eq!(inputs.len(), 1);
+        assert_eq!(inputs[0].outpoint.txid, funding_txid.to_string());
+        assert_eq!(inputs[0].outpoint.vout, 0);
+        assert_eq!(inputs[0].amount, Some(50_000));
+        assert!(inputs[0].address.is_some());
+
+        // the transaction spent by the funding transaction is unknown
+        let inputs = test_wallet.get_tx_inputs(funding_txid.to_string()).unwrap();
+        assert_eq!(inputs[0].amount, None);
+        assert_eq!(inputs[0].address, None);
+    }
+
+    #[test]
+    fn test_calculate_fee() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let sweep_address = test_address();
+        let tx_builder_result = test_wallet.sweep(Arc::new(sweep_address), 2.0).unwrap();
+        let tx = tx_builder_result.psbt.extract_tx();
+
+        assert_eq!(test_wallet.calculate_fee(tx.clone()).unwrap(), 220);
+        // the unsigned transaction is lighter than the signed one the fee was computed for
+        assert!(test_wallet.calculate_fee_rate(tx).unwrap().as_sat_per_vb() > 2.0);
+
+        let unknown_prevout_tx = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: unknown_outpoint(),
+                ..Default::default()
+            }],
+            output: vec![],
         };
-        let drain_to_address = "tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt".to_string();
-        let drain_to_script = crate::Address::new(drain_to_address)
+        let result = test_wallet.calculate_fee(Arc::new(unknown_prevout_tx.into()));
+        assert_matches!(result, Err(BdkError::Generic { .. }));
+
+        let overflowing_tx = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: u64::MAX,
+                    script_pubkey: Default::default(),
+                };
+                2
+            ],
+        };
+        let result = test_wallet.calculate_fee(Arc::new(overflowing_tx.into()));
+        assert_matches!(result, Err(BdkError::Generic { .. }));
+    }
+
+    #[test]
+    fn test_get_tx_outputs() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, txid) = funded_test_wallet(test_wpkh);
+
+        let outputs = test_wallet.get_tx_outputs(txid.to_string()).unwrap();
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].amount, 50_000);
+        assert!(outputs[0].is_mine);
+        assert!(outputs[0].address.is_some());
+
+        let unknown_txid = "312f1733badab22dc26b8dcbc83ba5629fb7b493af802e8abe07d865e49629c5";
+        assert_matches!(
+            test_wallet.get_tx_outputs(unknown_txid.to_string()),
+            Err(BdkError::TransactionNotFound { .. })
+        );
+    }
+
+    struct TestSigner {
+        master_key: ExtendedPrivKey,
+    }
+
+    impl crate::Signer for TestSigner {
+        fn sign(
+            &self,
+            sighash: Vec<u8>,
+            _public_key: String,
+            derivation_path: String,
+        ) -> Option<Vec<u8>> {
+            let secp = Secp256k1::new();
+            let path = DerivationPath::from_str(derivation_path.as_str()).unwrap();
+            let key = self.master_key.derive_priv(&secp, &path).unwrap();
+            let message = Message::from_slice(&sighash).unwrap();
+            Some(
+                secp.sign_ecdsa(&message, &key.private_key)
+                    .serialize_der()
+                    .to_vec(),
+            )
+        }
+    }
+
+    #[test]
+    fn test_add_signer() {
+        let test_wpkh = "wpkh([d1d04177]tpubD6NzVbkrYhZ4WywdEfYbbd62yuvqLjAZuPsNyvzCNV85JekAEMbKHWSHLF9h3j45SxewXDcLv328B1SEZrxg4iwGfmdt1pDFjZiTkGiFqGa/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let sweep_address = test_address();
+        let tx_builder_result = test_wallet.sweep(Arc::new(sweep_address), 2.0).unwrap();
+
+        // the watch-only wallet can't sign by itself
+        assert!(!test_wallet.sign(&tx_builder_result.psbt, None).unwrap());
+
+        let master_key = ExtendedPrivKey::from_str("tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h").unwrap();
+        test_wallet
+            .add_signer(
+                KeychainKind::External,
+                "d1d04177".to_string(),
+                Box::new(TestSigner { master_key }),
+            )
+            .unwrap();
+        assert!(test_wallet.sign(&tx_builder_result.psbt, None).unwrap());
+    }
+
+    #[test]
+    fn test_bump_fee_errors() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, funding_txid) = funded_test_wallet(test_wpkh);
+
+        let unknown_txid = "312f1733badab22dc26b8dcbc83ba5629fb7b493af802e8abe07d865e49629c5";
+        assert_matches!(
+            BumpFeeTxBuilder::new(unknown_txid.to_string(), 5.0).finish(&test_wallet),
+            Err(BdkError::TransactionNotFound { .. })
+        );
+
+        assert_matches!(
+            BumpFeeTxBuilder::new(funding_txid.to_string(), 5.0).finish(&test_wallet),
+            Err(BdkError::TransactionConfirmed { .. })
+        );
+
+        // an unconfirmed transaction which doesn't signal RBF
+        let recipient = test_recipient();
+        let tx = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt
+            .extract_tx();
+        let txid = tx.txid();
+        test_wallet.insert_tx(tx, None).unwrap();
+        assert_matches!(
+            BumpFeeTxBuilder::new(txid, 5.0).finish(&test_wallet),
+            Err(BdkError::IrreplaceableTransaction { .. })
+        );
+    }
+
+    #[test]
+    fn test_bump_fee_for_transaction() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+
+        // a transaction signaling RBF which was never inserted in the wallet database
+        let psbt = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .enable_rbf()
+            .fee_rate(1.0)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+        let tx = psbt.extract_tx();
+        let txid = tx.txid();
+        assert_matches!(
+            BumpFeeTxBuilder::new(txid.clone(), 5.0).finish(&test_wallet),
+            Err(BdkError::TransactionNotFound { .. })
+        );
+
+        // the previous transactions are needed
+        let mut psbt_without_previous_txs = psbt.internal.lock().unwrap().clone();
+        psbt_without_previous_txs.inputs[0].non_witness_utxo = None;
+        let psbt_without_previous_txs = Arc::new(PartiallySignedTransaction {
+            internal: Mutex::new(psbt_without_previous_txs),
+        });
+        assert_matches!(
+            BumpFeeTxBuilder::new(txid.clone(), 5.0).finish_for_transaction(
+                &test_wallet,
+                tx.clone(),
+                psbt_without_previous_txs
+            ),
+            Err(BdkError::Generic { .. })
+        );
+
+        let bumped_psbt = BumpFeeTxBuilder::new(txid, 5.0)
+            .finish_for_transaction(&test_wallet, tx, psbt.clone())
+            .unwrap();
+        assert!(bumped_psbt.fee_amount().unwrap() > psbt.fee_amount().unwrap());
+    }
+
+    #[test]
+    fn test_bump_fee_for_transaction_failed() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+        let wallet_state = |wallet: &Wallet| {
+            let balance = wallet.get_balance().unwrap();
+            let utxos: Vec<(crate::OutPoint, bool)> = wallet
+                .list_unspent()
+                .unwrap()
+                .into_iter()
+                .map(|utxo| (utxo.outpoint, utxo.is_spent))
+                .collect();
+            let transaction_count = wallet.list_transactions(false).unwrap().len();
+            (balance.confirmed, balance.total, utxos, transaction_count)
+        };
+        let state_before = wallet_state(&test_wallet);
+
+        // the transaction doesn't signal RBF, so the bump fails once it is inserted
+        let psbt = TxBuilder::new()
+            .add_recipient(recipient, 25_000)
+            .fee_rate(1.0)
+            .finish(&test_wallet)
+            .unwrap()
+            .psbt;
+        let tx = psbt.extract_tx();
+        let txid = tx.txid();
+        assert_matches!(
+            BumpFeeTxBuilder::new(txid.clone(), 5.0).finish_for_transaction(&test_wallet, tx, psbt),
+            Err(BdkError::IrreplaceableTransaction { .. })
+        );
+
+        // nothing was saved, the output spent by the transaction is still unspent
+        assert_eq!(wallet_state(&test_wallet), state_before);
+        assert_matches!(
+            BumpFeeTxBuilder::new(txid, 5.0).finish(&test_wallet),
+            Err(BdkError::TransactionNotFound { .. })
+        );
+    }
+
+    #[test]
+    fn test_send_saves_transaction() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, funding_txid) = funded_test_wallet(test_wpkh);
+        let recipient = test_recipient();
+        let spent_utxo = test_wallet.list_unspent().unwrap().remove(0).outpoint;
+        assert_eq!(spent_utxo.txid, funding_txid.to_string());
+
+        // the broadcast succeeds without a server
+        let txid = test_wallet
+            .send_with(
+                vec![ScriptAmount {
+                    script: recipient,
+                    amount: 25_000,
+                }],
+                1.0,
+                true,
+                |_| Ok(()),
+            )
+            .unwrap();
+
+        // the spent output is gone and the change of the unconfirmed transaction replaces it
+        let utxos = test_wallet.list_unspent().unwrap();
+        assert!(utxos.iter().all(|utxo| utxo.outpoint != spent_utxo));
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint.txid, txid);
+        let transactions = test_wallet
+            .list_transactions_filtered(TransactionFilter::Unconfirmed, false)
+            .unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].txid, txid);
+    }
+
+    #[test]
+    fn test_send_not_finalized() {
+        // a watch-only wallet can't sign
+        let test_wpkh = "wpkh([d1d04177/84'/1'/0']tpubDDNxbq17egjFk2edjv8oLnzxk52zny9aAYNv9CMqTzA4mQDiQq818sEkNe9Gzmd4QU8558zftqbfoVBDQorG3E4Wq26tB2JeE4KUoahLkx6/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        // creating an esplora blockchain doesn't connect to the server, so the send must fail
+        // before broadcasting
+        let blockchain = offline_esplora();
+        let recipient = test_recipient();
+
+        let error = test_wallet
+            .send(
+                &blockchain,
+                vec![ScriptAmount {
+                    script: recipient,
+                    amount: 25_000,
+                }],
+                1.0,
+                true,
+            )
+            .unwrap_err();
+        assert_matches!(error, BdkError::Generic { error_message: message } => {
+            assert!(message.contains("wasn't broadcast"));
+        });
+    }
+
+    #[test]
+    fn test_sweep() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let sweep_address = test_address();
+
+        let tx_builder_result = test_wallet.sweep(Arc::new(sweep_address), 2.0).unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+
+        // all funds are spent to a single output, minus the fee
+        assert_eq!(psbt.inputs.len(), 1);
+        assert_eq!(psbt.outputs.len(), 1);
+        assert_eq!(psbt.unsigned_tx.output.get(0).unwrap().value, 49_780_u64);
+        assert_eq!(tx_builder_result.transaction_details.sent, 50_000);
+        assert_eq!(tx_builder_result.transaction_details.fee, Some(220));
+    }
+
+    #[test]
+    fn test_flush() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let path = std::env::temp_dir().join(format!("bdk-ffi-test-flush-{}", std::process::id()));
+        let database_config = DatabaseConfig::Sled {
+            config: SledDbConfiguration {
+                path: path.to_str().unwrap().to_string(),
+                tree_name: "test_flush".to_string(),
+            },
+        };
+        let wallet = Wallet::new(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+            database_config,
+        )
+        .unwrap();
+        let address = wallet.get_address(AddressIndex::New).unwrap();
+        assert!(wallet.flush().is_ok());
+        drop(wallet);
+
+        // the revealed address index was persisted
+        let database_config = DatabaseConfig::Sled {
+            config: SledDbConfiguration {
+                path: path.to_str().unwrap().to_string(),
+                tree_name: "test_flush".to_string(),
+            },
+        };
+        let wallet = Wallet::new(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+            database_config,
+        )
+        .unwrap();
+        assert_eq!(
+            wallet
+                .get_address(AddressIndex::LastUnused)
+                .unwrap()
+                .address
+                .as_string(),
+            address.address.as_string()
+        );
+        drop(wallet);
+        std::fs::remove_dir_all(path).unwrap();
+
+        let wallet = Wallet::new(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+            DatabaseConfig::Memory,
+        )
+        .unwrap();
+        assert!(wallet.flush().is_ok());
+    }
+
+    #[test]
+    fn test_psbt_outputs_info() {
+        let test_wpkh = "wpkh(cVpPVruEDdmutPzisEsYvtST1usBR3ntr8pXSyt6D2YYqXRyPcFW)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let recipient_script = test_recipient();
+
+        let tx_builder_result = TxBuilder::new()
+            .add_recipient(recipient_script.clone(), 25_000)
+            .finish(&test_wallet)
+            .unwrap();
+        let outputs_info = test_wallet
+            .get_psbt_outputs_info(&tx_builder_result.psbt)
+            .unwrap();
+        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
+
+        // one recipient output and one change output
+        assert_eq!(outputs_info.len(), 2);
+        for (tx_out, info) in psbt.unsigned_tx.output.iter().zip(outputs_info.iter()) {
+            if tx_out.script_pubkey == recipient_script.script {
+                assert!(!info.is_mine);
+                assert!(info.derivation_path.is_none());
+            } else {
+                assert!(info.is_mine);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_tx() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let descriptor = Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap();
+        let wallet = Wallet::new(
+            Arc::new(descriptor),
+            None,
+            Network::Regtest,
+            DatabaseConfig::Memory,
+        )
+        .unwrap();
+        let receive_script = wallet
+            .get_address(AddressIndex::New)
+            .unwrap()
+            .address
+            .script_pubkey();
+
+        let tx = receive_tx(receive_script.script.clone(), 50_000);
+        wallet
+            .get_wallet()
+            .sync(&TestChain { height: 120 }, BdkSyncOptions::default())
+            .unwrap();
+        let sync_time = wallet.get_wallet().database().get_sync_time().unwrap();
+        assert!(sync_time.is_some());
+
+        let txid = tx.txid().to_string();
+        wallet
+            .insert_tx(
+                Arc::new(tx.into()),
+                Some(BlockTime {
+                    height: 100,
+                    timestamp: 1_600_000_000,
+                }),
+            )
+            .unwrap();
+        // inserting a transaction isn't a sync
+        assert_eq!(
+            wallet.get_wallet().database().get_sync_time().unwrap(),
+            sync_time
+        );
+
+        let transactions = wallet.list_transactions(false).unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].txid, txid);
+        assert_eq!(transactions[0].received, 50_000);
+        assert_eq!(transactions[0].sent, 0);
+        assert_eq!(transactions[0].net, 50_000);
+        // the previous output is unknown so the fee can't be calculated
+        assert!(transactions[0].fee.is_none());
+        assert_eq!(
+            transactions[0].confirmation_time.as_ref().unwrap().height,
+            100
+        );
+        assert_eq!(transactions[0].confirmation_height, Some(100));
+        assert_eq!(transactions[0].confirmation_timestamp, Some(1_600_000_000));
+
+        let unspent = wallet.list_unspent().unwrap();
+        assert_eq!(unspent.len(), 1);
+        assert_eq!(unspent[0].txout.value, 50_000);
+    }
+
+    #[test]
+    fn test_insert_tx_amount_overflow() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        let receive_script = wallet
+            .get_address(AddressIndex::New)
+            .unwrap()
+            .address
+            .script_pubkey();
+
+        let tx = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: u64::MAX,
+                    script_pubkey: receive_script.script.clone(),
+                };
+                2
+            ],
+        };
+        assert_matches!(
+            wallet.insert_tx(Arc::new(tx.into()), None),
+            Err(BdkError::Generic { .. })
+        );
+        // nothing was written
+        assert!(wallet.list_transactions(false).unwrap().is_empty());
+        assert!(wallet.list_unspent().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_get_balance_at_height() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        let mut scripts = (0..2).map(|_| {
+            wallet
+                .get_address(AddressIndex::New)
+                .unwrap()
+                .address
+                .script_pubkey()
+                .script
+                .clone()
+        });
+
+        let receive_tx = receive_tx(scripts.next().unwrap(), 50_000);
+        // spends the received output, sending 20_000 back to the wallet
+        let spend_tx = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: OutPoint::new(receive_tx.txid(), 0),
+                ..Default::default()
+            }],
+            output: vec![
+                TxOut {
+                    value: 29_000,
+                    script_pubkey: crate::Address::new(
+                        "bcrt1q5g0mq6dkmwzvxscqwgc932jhgcxuqqkjv09tkj".to_string(),
+                    )
+                    .unwrap()
+                    .script_pubkey()
+                    .script
+                    .clone(),
+                },
+                TxOut {
+                    value: 20_000,
+                    script_pubkey: scripts.next().unwrap(),
+                },
+            ],
+        };
+        for (tx, height) in [(receive_tx, 100), (spend_tx, 200)] {
+            wallet
+                .insert_tx(
+                    Arc::new(tx.into()),
+                    Some(BlockTime {
+                        height,
+                        timestamp: 1_600_000_000 + u64::from(height),
+                    }),
+                )
+                .unwrap();
+        }
+
+        assert_eq!(wallet.get_balance_at_height(99).unwrap().total, 0);
+        let balance = wallet.get_balance_at_height(150).unwrap();
+        assert_eq!(balance.confirmed, 50_000);
+        assert_eq!(balance.total, 50_000);
+        assert_eq!(wallet.get_balance_at_height(200).unwrap().confirmed, 20_000);
+        assert_eq!(
+            wallet.get_balance_at_height(250).unwrap().confirmed,
+            wallet.get_balance().unwrap().confirmed
+        );
+    }
+
+    #[test]
+    fn test_transaction_fee_rate() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let result = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .fee_rate(5.0)
+            .finish(&wallet)
+            .unwrap();
+        let tx = wallet
+            .sign_and_extract(&result.psbt, None)
+            .unwrap()
+            .unwrap();
+        wallet.insert_tx(tx.clone(), None).unwrap();
+
+        let details = wallet
+            .list_transactions(false)
+            .unwrap()
+            .into_iter()
+            .find(|details| details.txid == tx.txid())
+            .unwrap();
+        let fee = details.fee.unwrap();
+        assert_eq!(fee, result.transaction_details.fee.unwrap());
+        let fee_rate = details.fee_rate.unwrap();
+        assert_eq!(fee_rate, fee as f32 / tx.internal.vsize() as f32);
+        // the size of the signatures was estimated when building the transaction
+        assert!((5.0..5.1).contains(&fee_rate));
+    }
+
+    #[test]
+    fn test_list_transactions_filtered() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (wallet, funding_txid) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let psbt = TxBuilder::new()
+            .drain_wallet()
+            .drain_to(drain_to_script)
+            .finish(&wallet)
+            .unwrap()
+            .psbt;
+        let tx = wallet.sign_and_extract(&psbt, None).unwrap().unwrap();
+        wallet.insert_tx(tx.clone(), None).unwrap();
+
+        let txids = |filter| -> Vec<String> {
+            wallet
+                .list_transactions_filtered(filter, false)
+                .unwrap()
+                .into_iter()
+                .map(|details| details.txid)
+                .collect()
+        };
+        assert_eq!(txids(TransactionFilter::All).len(), 2);
+        assert_eq!(
+            txids(TransactionFilter::Confirmed),
+            vec![funding_txid.to_string()]
+        );
+        assert_eq!(txids(TransactionFilter::Unconfirmed), vec![tx.txid()]);
+    }
+
+    #[test]
+    fn test_list_transactions_conflicts() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        let receive_script = wallet
+            .get_address(AddressIndex::New)
+            .unwrap()
+            .address
+            .script_pubkey();
+        let previous_output = unknown_outpoint();
+
+        // a transaction and its replacement paying a higher fee
+        let mut txids = Vec::new();
+        for value in [50_000, 49_000] {
+            let tx = Transaction {
+                version: 1,
+                lock_time: PackedLockTime(0),
+                input: vec![TxIn {
+                    previous_output,
+                    ..Default::default()
+                }],
+                output: vec![TxOut {
+                    value,
+                    script_pubkey: receive_script.script.clone(),
+                }],
+            };
+            txids.push(tx.txid().to_string());
+            wallet.insert_tx(Arc::new(tx.into()), None).unwrap();
+        }
+
+        let transactions = wallet.list_transactions(false).unwrap();
+        assert_eq!(transactions.len(), 2);
+        for details in &transactions {
+            let other = txids.iter().find(|txid| **txid != details.txid).unwrap();
+            assert_eq!(details.conflicts, vec![other.clone()]);
+            assert!(details.transaction.is_none());
+        }
+    }
+
+    #[test]
+    fn test_list_transactions_coinbase_no_conflicts() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        let receive_script = wallet
+            .get_address(AddressIndex::New)
             .unwrap()
+            .address
             .script_pubkey();
-        let tx_builder = TxBuilder::new()
-            .drain_wallet()
-            .drain_to(drain_to_script.clone());
-        assert!(tx_builder.drain_wallet);
-        assert_eq!(tx_builder.drain_to, Some(drain_to_script.script.clone()));
 
-        let tx_builder_result = tx_builder.finish(&test_wallet).unwrap();
-        let psbt = tx_builder_result.psbt.internal.lock().unwrap().clone();
-        let tx_details = tx_builder_result.transaction_details;
+        // the coinbase transactions of two blocks both spend the null outpoint
+        for (height, value) in [(150, 5_000_000_000), (151, 4_999_000_000)] {
+            let coinbase = Transaction {
+                version: 1,
+                lock_time: PackedLockTime(0),
+                input: vec![TxIn::default()],
+                output: vec![TxOut {
+                    value,
+                    script_pubkey: receive_script.script.clone(),
+                }],
+            };
+            assert!(coinbase.is_coin_base());
+            wallet
+                .insert_tx(
+                    Arc::new(coinbase.into()),
+                    Some(BlockTime {
+                        height,
+                        timestamp: 1_600_000_000,
+                    }),
+                )
+                .unwrap();
+        }
 
-        // confirm one input with 50,000 sats
-        assert_eq!(psbt.inputs.len(), 1);
-        let input_value = psbt
-            .inputs
-            .get(0)
-            .cloned()
-            .unwrap()
-            .non_witness_utxo
-            .unwrap()
-            .output
-            .get(0)
-            .unwrap()
-            .value;
-        assert_eq!(input_value, 50_000_u64);
+        let transactions = wallet.list_transactions(false).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert!(transactions
+            .iter()
+            .all(|details| details.conflicts.is_empty()));
+    }
 
-        // confirm one output to correct address with all sats - fee
-        assert_eq!(psbt.outputs.len(), 1);
-        let output_address = Address::from_script(
-            &psbt
-                .unsigned_tx
-                .output
-                .get(0)
-                .cloned()
-                .unwrap()
-                .script_pubkey,
-            Network::Testnet,
+    #[test]
+    fn test_try_sync() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        // creating an esplora blockchain doesn't connect to the server
+        let blockchain = offline_esplora();
+
+        // the wallet is in use, e.g. by a sync on another thread
+        let wallet_guard = wallet.get_wallet();
+        assert_matches!(
+            wallet.try_sync(&blockchain, None),
+            Err(BdkError::Generic { error_message: message }) if message == "sync already in progress"
+        );
+        drop(wallet_guard);
+
+        // the blockchain is in use by another wallet's sync
+        let blockchain_guard = blockchain.get_blockchain();
+        assert_matches!(
+            wallet.try_sync(&blockchain, None),
+            Err(BdkError::Generic { error_message: message }) if message == "sync already in progress"
+        );
+        drop(blockchain_guard);
+    }
+
+    #[test]
+    fn test_sync_report() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
         )
         .unwrap();
+        let receive_script = wallet
+            .get_address(AddressIndex::New)
+            .unwrap()
+            .address
+            .script_pubkey();
+        let tx: crate::Transaction = receive_tx(receive_script.script.clone(), 50_000).into();
+        let tx = Arc::new(tx);
+
+        // the changes are found by comparing the transactions before and after the sync
+        let before = wallet.confirmation_heights().unwrap();
+        wallet.insert_tx(tx.clone(), None).unwrap();
+        let unconfirmed = wallet.confirmation_heights().unwrap();
         assert_eq!(
-            output_address,
-            Address::from_str("tb1ql7w62elx9ucw4pj5lgw4l028hmuw80sndtntxt").unwrap()
+            SyncReport::new(&before, &unconfirmed),
+            SyncReport {
+                new_transactions: 1,
+                updated_confirmations: 0,
+            }
         );
-        let output_value = psbt.unsigned_tx.output.get(0).cloned().unwrap().value;
-        assert_eq!(output_value, 49_890_u64); // input - fee
 
+        wallet
+            .insert_tx(
+                tx,
+                Some(BlockTime {
+                    height: 100,
+                    timestamp: 1_600_000_000,
+                }),
+            )
+            .unwrap();
+        let confirmed = wallet.confirmation_heights().unwrap();
         assert_eq!(
-            tx_details.txid,
-            "312f1733badab22dc26b8dcbc83ba5629fb7b493af802e8abe07d865e49629c5"
+            SyncReport::new(&unconfirmed, &confirmed),
+            SyncReport {
+                new_transactions: 0,
+                updated_confirmations: 1,
+            }
         );
-        assert_eq!(tx_details.received, 0);
-        assert_eq!(tx_details.sent, 50000);
-        assert!(tx_details.fee.is_some());
-        assert_eq!(tx_details.fee.unwrap(), 110);
-        assert!(tx_details.confirmation_time.is_none());
+        assert_eq!(
+            SyncReport::new(&confirmed, &confirmed),
+            SyncReport {
+                new_transactions: 0,
+                updated_confirmations: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_list_immature_utxos() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        let receive_script = wallet
+            .get_address(AddressIndex::New)
+            .unwrap()
+            .address
+            .script_pubkey();
+
+        // the only input of a coinbase transaction spends the null outpoint
+        let coinbase = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 5_000_000_000,
+                script_pubkey: receive_script.script.clone(),
+            }],
+        };
+        assert!(coinbase.is_coin_base());
+        wallet
+            .insert_tx(
+                Arc::new(coinbase.into()),
+                Some(BlockTime {
+                    height: 150,
+                    timestamp: 1_600_000_000,
+                }),
+            )
+            .unwrap();
+
+        let immature_utxos = wallet.list_immature_utxos(Some(200)).unwrap();
+        assert_eq!(immature_utxos.len(), 1);
+        assert_eq!(immature_utxos[0].maturity_height, 250);
+        assert_eq!(immature_utxos[0].utxo.txout.value, 5_000_000_000);
+        assert!(wallet.list_immature_utxos(Some(250)).unwrap().is_empty());
+
+        // the wallet was never synced, so all its coinbase outputs are immature
+        let immature_utxos = wallet.list_immature_utxos(None).unwrap();
+        assert_eq!(immature_utxos.len(), 1);
+        assert_eq!(immature_utxos[0].utxo.confirmation_height, Some(150));
+        assert_eq!(immature_utxos[0].maturity_height, 250);
+
+        // the output can be spent in the block after the synced height
+        wallet
+            .get_wallet()
+            .sync(&TestChain { height: 249 }, BdkSyncOptions::default())
+            .unwrap();
+        assert!(wallet.list_immature_utxos(None).unwrap().is_empty());
     }
 
     #[test]
@@ -730,6 +3631,435 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_new_with_change_script_type() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let descriptor = Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap();
+        let wallet = Wallet::new_with_change_script_type(
+            Arc::new(descriptor),
+            ScriptType::P2tr,
+            Network::Regtest,
+            DatabaseConfig::Memory,
+        )
+        .unwrap();
+
+        assert_eq!(
+            wallet
+                .get_address(AddressIndex::Peek { index: 0 })
+                .unwrap()
+                .address
+                .as_string(),
+            "bcrt1qqjn9gky9mkrm3c28e5e87t5akd3twg6xezp0tv"
+        );
+        assert!(wallet
+            .get_internal_address(AddressIndex::Peek { index: 0 })
+            .unwrap()
+            .address
+            .as_string()
+            .starts_with("bcrt1p"));
+    }
+
+    #[test]
+    fn test_new_wallet_same_keychains() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let descriptor =
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap());
+
+        let result = Wallet::new(
+            descriptor.clone(),
+            Some(descriptor),
+            Network::Regtest,
+            DatabaseConfig::Memory,
+        );
+        assert!(matches!(result, Err(BdkError::Generic { .. })));
+    }
+
+    #[test]
+    fn test_get_last_used_index() {
+        let external = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let internal = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/1/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(external.to_string(), Network::Regtest).unwrap()),
+            Some(Arc::new(
+                Descriptor::new(internal.to_string(), Network::Regtest).unwrap(),
+            )),
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(
+            wallet.get_last_used_index(KeychainKind::External).unwrap(),
+            None
+        );
+
+        // revealing addresses doesn't use them
+        wallet.reveal_to(KeychainKind::External, 20).unwrap();
+        let scripts: Vec<_> = [5, 2]
+            .iter()
+            .map(|index| {
+                wallet
+                    .get_address(AddressIndex::Peek { index: *index })
+                    .unwrap()
+                    .address
+                    .script_pubkey()
+                    .script
+                    .clone()
+            })
+            .collect();
+        let tx = Transaction {
+            version: 1,
+            lock_time: PackedLockTime(0),
+            input: vec![TxIn {
+                previous_output: unknown_outpoint(),
+                ..Default::default()
+            }],
+            output: scripts
+                .into_iter()
+                .map(|script_pubkey| TxOut {
+                    value: 10_000,
+                    script_pubkey,
+                })
+                .collect(),
+        };
+        wallet.insert_tx(Arc::new(tx.into()), None).unwrap();
+
+        assert_eq!(
+            wallet.get_last_used_index(KeychainKind::External).unwrap(),
+            Some(5)
+        );
+        assert_eq!(
+            wallet.get_last_used_index(KeychainKind::Internal).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_clear_cache() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (wallet, _) = funded_test_wallet(test_wpkh);
+        wallet.reveal_to(KeychainKind::External, 5).unwrap();
+        assert_eq!(wallet.list_transactions(false).unwrap().len(), 1);
+        assert_eq!(wallet.list_unspent().unwrap().len(), 1);
+
+        wallet.clear_cache().unwrap();
+        assert!(wallet.list_transactions(false).unwrap().is_empty());
+        assert!(wallet.list_unspent().unwrap().is_empty());
+        assert_eq!(wallet.get_balance().unwrap().total, 0);
+        assert!(wallet
+            .get_wallet()
+            .database()
+            .iter_raw_txs()
+            .unwrap()
+            .is_empty());
+        // the revealed addresses are kept
+        assert_eq!(wallet.get_address(AddressIndex::New).unwrap().index, 6);
+    }
+
+    #[test]
+    fn test_next_unused_address() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(wallet.get_address(AddressIndex::New).unwrap().index, 0);
+        // the address at index 1 is used without having been handed out
+        let used_script = wallet
+            .get_address(AddressIndex::Peek { index: 1 })
+            .unwrap()
+            .address
+            .script_pubkey();
+        let tx = receive_tx(used_script.script.clone(), 10_000);
+        wallet.insert_tx(Arc::new(tx.into()), None).unwrap();
+
+        let indexes: Vec<u32> = (0..2)
+            .map(|_| {
+                wallet
+                    .next_unused_address(KeychainKind::External)
+                    .unwrap()
+                    .index
+            })
+            .collect();
+        assert_eq!(indexes, vec![2, 3]);
+    }
+
+    #[test]
+    fn test_export_import_backup() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let descriptor = Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap();
+        let change_descriptor =
+            Descriptor::new(test_wpkh.replace("/0/*", "/1/*"), Network::Regtest).unwrap();
+        let wallet = Wallet::new_no_persist(
+            Arc::new(descriptor),
+            Some(Arc::new(change_descriptor)),
+            Network::Regtest,
+        )
+        .unwrap();
+        for _ in 0..3 {
+            wallet.get_address(AddressIndex::New).unwrap();
+        }
+        let last_address = wallet.get_address(AddressIndex::Peek { index: 2 }).unwrap();
+
+        let backup = wallet.export_backup().unwrap();
+        assert!(backup.contains("tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE"));
+        let imported = Wallet::import_backup(backup.clone(), DatabaseConfig::Memory).unwrap();
+        assert_eq!(imported.export_backup().unwrap(), backup);
+        assert_eq!(imported.network(), Network::Regtest);
+        // the revealed addresses aren't handed out again
+        let next_address = imported.get_address(AddressIndex::New).unwrap();
+        assert_eq!(next_address.index, 3);
+        assert_ne!(
+            next_address.address.as_string(),
+            last_address.address.as_string()
+        );
+        assert!(imported.get_signers().unwrap()[0].has_secret);
+
+        assert_matches!(
+            Wallet::import_backup("{}".to_string(), DatabaseConfig::Memory),
+            Err(BdkError::Generic { .. })
+        );
+    }
+
+    #[test]
+    fn test_peek_script() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            Some(Arc::new(
+                Descriptor::new(test_wpkh.replace("/0/*", "/1/*"), Network::Regtest).unwrap(),
+            )),
+            Network::Regtest,
+        )
+        .unwrap();
+
+        let script = wallet.peek_script(KeychainKind::External, 5).unwrap();
+        let address = wallet.get_address(AddressIndex::Peek { index: 5 }).unwrap();
+        assert_eq!(script.script, address.address.script_pubkey().script);
+        let change_script = wallet.peek_script(KeychainKind::Internal, 5).unwrap();
+        let change_address = wallet
+            .get_internal_address(AddressIndex::Peek { index: 5 })
+            .unwrap();
+        assert_eq!(
+            change_script.script,
+            change_address.address.script_pubkey().script
+        );
+        assert_ne!(script.script, change_script.script);
+        // peeking doesn't reveal the script
+        assert_eq!(wallet.get_address(AddressIndex::New).unwrap().index, 0);
+
+        assert_matches!(
+            wallet.peek_script(KeychainKind::External, 1 << 31),
+            Err(BdkError::Generic { .. })
+        );
+    }
+
+    #[test]
+    fn test_get_last_synced_height() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(wallet.get_last_synced_height().unwrap(), None);
+        // database updates aren't a sync
+        wallet.reveal_to(KeychainKind::External, 5).unwrap();
+        assert_eq!(wallet.get_last_synced_height().unwrap(), None);
+
+        // a blockchain sync records the height of the chain tip
+        wallet
+            .get_wallet()
+            .sync(&TestChain { height: 120 }, BdkSyncOptions::default())
+            .unwrap();
+        assert_eq!(wallet.get_last_synced_height().unwrap(), Some(120));
+        wallet.reveal_to(KeychainKind::External, 10).unwrap();
+        assert_eq!(wallet.get_last_synced_height().unwrap(), Some(120));
+
+        wallet.clear_cache().unwrap();
+        assert_eq!(wallet.get_last_synced_height().unwrap(), None);
+
+        // a chain with only the genesis block is synced at height 0
+        wallet
+            .get_wallet()
+            .sync(&TestChain { height: 0 }, BdkSyncOptions::default())
+            .unwrap();
+        assert_eq!(wallet.get_last_synced_height().unwrap(), Some(0));
+    }
+
+    #[test]
+    fn test_reveal_to() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+            DatabaseConfig::Memory,
+        )
+        .unwrap();
+
+        wallet.reveal_to(KeychainKind::External, 50).unwrap();
+        assert_eq!(
+            wallet.get_address(AddressIndex::LastUnused).unwrap().index,
+            50
+        );
+        assert_eq!(wallet.get_address(AddressIndex::New).unwrap().index, 51);
+
+        // the revealed index doesn't go backwards
+        wallet.reveal_to(KeychainKind::External, 10).unwrap();
+        assert_eq!(
+            wallet.get_address(AddressIndex::LastUnused).unwrap().index,
+            51
+        );
+    }
+
+    #[test]
+    fn test_get_signers() {
+        let descriptor = "wsh(multi(2,tprv8ZgxMBicQKsPdWuqM1t1CDRvQtQuBPyfL6GbhQwtxDKgUAVPbxmj71pRA8raTqLrec5LyTs5TqCxdABcZr77bt2KyWA5bizJHnC4g4ysm4h/0/*,tpubD9oaCiP1MPmQdndm7DCD3D3QU34pWd6BbKSRedoZF1UJcNhEk3PJwkALNYkhxeTKL29oGNR7psqvT1KZydCGqUDEKXN6dVQJY2R8ooLPy8m/0/*,tpubDBrgjcxBxnXyL575sHdkpKohWu5qHKoQ7TJXKNrYznh5fVEGBv89hA8ENW7A8MFVpFUSvgLqc4Nj1WZcpePX6rrxviVtPowvMuGF5rdT2Vi/0/*))";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(descriptor.to_string(), Network::Testnet).unwrap()),
+            None,
+            Network::Testnet,
+        )
+        .unwrap();
+        let signers = wallet.get_signers().unwrap();
+        assert_eq!(signers.len(), 3);
+        assert_eq!(
+            signers[0],
+            SignerInfo {
+                fingerprint: "d1d04177".to_string(),
+                has_secret: true,
+            }
+        );
+        assert!(signers[1..].iter().all(|signer| !signer.has_secret));
+    }
+
+    #[test]
+    fn test_sign_and_extract() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let tx_builder = TxBuilder::new().drain_wallet().drain_to(drain_to_script);
+        let psbt = tx_builder.finish(&test_wallet).unwrap().psbt;
+
+        let tx = test_wallet.sign_and_extract(&psbt, None).unwrap().unwrap();
+        assert_eq!(tx.txid(), psbt.txid());
+        assert_eq!(tx.internal.input[0].witness.len(), 2);
+
+        // the PSBT isn't finalized, e.g. when more signatures are needed
+        let psbt = tx_builder.finish(&test_wallet).unwrap().psbt;
+        let sign_options = SignOptions {
+            try_finalize: false,
+            ..Default::default()
+        };
+        assert!(test_wallet
+            .sign_and_extract(&psbt, Some(sign_options))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_sign_twice() {
+        let test_wpkh = "wpkh(tprv8ZgxMBicQKsPcwcD4gSnMti126ZiETsuX7qwrtMypr6FBwAP65puFn4v6c3jrN9VwtMRMph6nyT63NrfUL4C3nBzPcduzVSuHD7zbX2JKVc/0/*)";
+        let (test_wallet, _) = funded_test_wallet(test_wpkh);
+        let drain_to_script = test_recipient();
+        let tx_builder = TxBuilder::new().drain_wallet().drain_to(drain_to_script);
+
+        // signing a finalized PSBT again leaves it unchanged
+        let psbt = tx_builder.finish(&test_wallet).unwrap().psbt;
+        assert!(test_wallet.sign(&psbt, None).unwrap());
+        let signed = psbt.serialize();
+        assert!(test_wallet.sign(&psbt, None).unwrap());
+        assert_eq!(psbt.serialize(), signed);
+        assert_eq!(psbt.extract_tx().internal.input[0].witness.len(), 2);
+
+        // and so does signing a PSBT which isn't finalized again, without duplicate signatures
+        let sign_options = SignOptions {
+            trust_witness_utxo: false,
+            assume_height: None,
+            allow_all_sighashes: false,
+            remove_partial_sigs: true,
+            try_finalize: false,
+            sign_with_tap_internal_key: true,
+            allow_grinding: true,
+        };
+        let psbt = tx_builder.finish(&test_wallet).unwrap().psbt;
+        assert!(!test_wallet.sign(&psbt, Some(sign_options.clone())).unwrap());
+        let signed = psbt.serialize();
+        assert!(!test_wallet.sign(&psbt, Some(sign_options)).unwrap());
+        assert_eq!(psbt.serialize(), signed);
+        assert_eq!(psbt.partial_sigs_count(), vec![1]);
+        assert!(test_wallet.sign(&psbt, None).unwrap());
+    }
+
+    #[test]
+    fn test_signet_wallet() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let descriptor = Descriptor::new(test_wpkh.to_string(), Network::Signet).unwrap();
+        let wallet = Wallet::new_no_persist(Arc::new(descriptor), None, Network::Signet).unwrap();
+        assert_eq!(wallet.network(), Network::Signet);
+
+        // signet uses the same addresses as testnet
+        let address = wallet.get_address(AddressIndex::New).unwrap().address;
+        assert_eq!(address.network(), Network::Signet);
+        assert_eq!(
+            address.as_string(),
+            wallet_address_on(test_wpkh, Network::Testnet)
+        );
+        // so a parsed address can't tell them apart and reports testnet
+        let parsed_address = crate::Address::new(address.as_string()).unwrap();
+        assert_eq!(parsed_address.network(), Network::Testnet);
+        assert_eq!(
+            parsed_address
+                .script_pubkey()
+                .to_address(Network::Signet)
+                .unwrap()
+                .network(),
+            Network::Signet
+        );
+
+        // mainnet keys are rejected
+        let mainnet_wpkh = "wpkh(xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi/0/*)";
+        assert_matches!(
+            Descriptor::new(mainnet_wpkh.to_string(), Network::Signet),
+            Err(BdkError::Descriptor { .. })
+        );
+    }
+
+    fn wallet_address_on(descriptor: &str, network: Network) -> String {
+        Wallet::new_no_persist(
+            Arc::new(Descriptor::new(descriptor.to_string(), network).unwrap()),
+            None,
+            network,
+        )
+        .unwrap()
+        .get_address(AddressIndex::New)
+        .unwrap()
+        .address
+        .as_string()
+    }
+
+    #[test]
+    fn test_new_no_persist() {
+        let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";
+        let wallet = Wallet::new_no_persist(
+            Arc::new(Descriptor::new(test_wpkh.to_string(), Network::Regtest).unwrap()),
+            None,
+            Network::Regtest,
+        )
+        .unwrap();
+        assert_eq!(
+            wallet
+                .get_address(AddressIndex::New)
+                .unwrap()
+                .address
+                .as_string(),
+            "bcrt1qqjn9gky9mkrm3c28e5e87t5akd3twg6xezp0tv"
+        );
+        assert!(wallet.list_transactions(false).unwrap().is_empty());
+    }
+
     #[test]
     fn test_get_address() {
         let test_wpkh = "wpkh(tprv8hwWMmPE4BVNxGdVt3HhEERZhondQvodUY7Ajyseyhudr4WabJqWKWLr4Wi2r26CDaNCQhhxEftEaNzz7dPGhWuKFU4VULesmhEfZYyBXdE/0/*)";